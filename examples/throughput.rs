@@ -0,0 +1,80 @@
+//! Instruction throughput of `CPU::step`, and the opcode decode it relies
+//! on: the static decode table against the `HashMap` lookup it replaced.
+//!
+//! cargo run --release --example throughput
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::Instant;
+
+use rusty_nes::cpu::CPU;
+use rusty_nes::cpu_types::CpuVariant;
+use rusty_nes::instruction::{decode_table, Instruction, InstructionSet};
+
+const INSTRUCTIONS: u64 = 50_000_000;
+const LOOKUPS: u64 = 200_000_000;
+
+/// Times `LOOKUPS` decodes of a fixed opcode stream, returning lookups/s
+fn time_decode<F>(mut decode: F) -> f64
+where
+    F: FnMut(u8) -> Option<&'static Instruction>,
+{
+    let mut code: u8 = 0;
+    let mut cycles: u64 = 0;
+    let start = Instant::now();
+    for _ in 0..LOOKUPS {
+        code = code.wrapping_mul(5).wrapping_add(0x3b);
+        if let Some(instruction) = decode(black_box(code)) {
+            cycles += instruction.cycles as u64;
+        }
+    }
+    black_box(cycles);
+    LOOKUPS as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    // Before: a HashMap keyed by opcode, like the lazy_static map the
+    // decoder used to go through
+    let map: HashMap<u8, &'static Instruction> = InstructionSet::for_variant(CpuVariant::Ricoh2A03)
+        .iter()
+        .map(|instruction| (instruction.code, instruction))
+        .collect();
+    let before = time_decode(|code| map.get(&code).copied());
+
+    // After: the 256-entry table built at compile time
+    let table = decode_table(CpuVariant::Ricoh2A03);
+    let after = time_decode(|code| table[code as usize]);
+
+    println!(
+        "decode: HashMap {:.1}M lookups/s, static table {:.1}M lookups/s ({:.1}x)",
+        before / 1e6,
+        after / 1e6,
+        after / before,
+    );
+
+    // start: LDX #$00
+    // loop:  LDA $0200,X; CLC; ADC #$03; STA $0200,X; ROL $10; INX; BNE loop
+    //        JMP start
+    let program = vec![
+        0xa2, 0x00, 0xbd, 0x00, 0x02, 0x18, 0x69, 0x03, 0x9d, 0x00, 0x02, 0x26, 0x10, 0xe8,
+        0xd0, 0xf2, 0x4c, 0x00, 0x80,
+    ];
+
+    let mut cpu = CPU::new();
+    cpu.load(program);
+    cpu.reset();
+
+    let start = Instant::now();
+    for _ in 0..INSTRUCTIONS {
+        cpu.step().unwrap();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!(
+        "step: {} instructions, {} cycles in {:.3}s: {:.1}M instructions/s, {:.1} MHz",
+        INSTRUCTIONS,
+        cpu.cycles,
+        elapsed,
+        INSTRUCTIONS as f64 / elapsed / 1e6,
+        cpu.cycles as f64 / elapsed / 1e6,
+    );
+}
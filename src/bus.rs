@@ -0,0 +1,461 @@
+use crate::mapper::Mapper;
+
+/// Everything the CPU can reach through its address and data pins
+pub trait Bus {
+    /// A CPU read, with whatever side effects the device behind `addr` has
+    fn read(&mut self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, data: u8);
+
+    /// What a read of `addr` would return, without its side effects. Meant
+    /// for debuggers and trace logs.
+    fn peek(&self, addr: u16) -> u8;
+
+    /// Stores `data` at `addr` the way a loader or debugger would: ROM takes
+    /// it too, and nothing is latched on the data bus
+    fn poke(&mut self, addr: u16, data: u8) {
+        self.write(addr, data)
+    }
+
+    /// The bus's open bus latch, for a bus wrapped around this one to share.
+    /// `None` if the bus doesn't keep one.
+    fn open_bus(&self) -> Option<&OpenBus> {
+        None
+    }
+
+    fn open_bus_mut(&mut self) -> Option<&mut OpenBus> {
+        None
+    }
+
+    /// Lets the devices on the bus catch up with `cycles` CPU cycles
+    fn tick(&mut self, _cycles: u8) {}
+
+    /// The console's 2KB of internal RAM, for `CPU::power_on` to fill
+    /// without going through bus writes. `None` if the bus has none.
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+}
+
+/// 64KB of plain RAM with nothing else attached, for tests and for running
+/// programs outside of the NES
+#[derive(Clone)]
+pub struct FlatRam {
+    memory: [u8; 0x10000],
+}
+
+impl FlatRam {
+    pub fn new() -> FlatRam {
+        FlatRam { memory: [0; 0x10000] }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for FlatRam {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.memory[..RAM_SIZE])
+    }
+}
+
+/// The data bus keeps the last value driven on it. Reads that no device
+/// answers, and the bits a register leaves undriven, see that value. The
+/// slow decay of the real bus is not modelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OpenBus(u8);
+
+impl OpenBus {
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// `data` on the `driven` bits, the latched value on the rest
+    pub fn merge(&self, data: u8, driven: u8) -> u8 {
+        (data & driven) | (self.0 & !driven)
+    }
+
+    /// Records a value that went over the bus and passes it through
+    pub fn latch(&mut self, data: u8) -> u8 {
+        self.0 = data;
+        data
+    }
+}
+
+const RAM_SIZE: usize = 0x0800;
+const RAM_MIRRORS_END: u16 = 0x1FFF;
+const PPU_REGISTERS: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const APU_IO_REGISTERS: u16 = 0x4000;
+const APU_IO_REGISTERS_END: u16 = 0x4017;
+const APU_STATUS: u16 = 0x4015;
+const JOYPAD1: u16 = 0x4016;
+const JOYPAD2: u16 = 0x4017;
+const TEST_MODE: u16 = 0x4018;
+const TEST_MODE_END: u16 = 0x401F;
+const CARTRIDGE: u16 = 0x4020;
+
+/// The 2A03 address decoding, and the bus `CPU::new` runs on:
+///
+/// | Range         | Device                                     |
+/// |---------------|--------------------------------------------|
+/// | $0000-$1FFF   | 2KB internal RAM, mirrored four times      |
+/// | $2000-$3FFF   | PPU registers, mirrored every 8 bytes      |
+/// | $4000-$4017   | APU and I/O registers                      |
+/// | $4018-$401F   | CPU test mode, disabled on retail consoles |
+/// | $4020-$FFFF   | Cartridge, handled by the mapper           |
+///
+/// There is no PPU or APU yet, so the PPU registers read back whatever was
+/// last written to them. Of the APU and I/O registers only $4015 and the
+/// joypad ports are readable; they read back the last write on the bits
+/// they drive. Everything else reads as open bus.
+pub struct NesBus<M: Mapper> {
+    open_bus: OpenBus,
+    ram: [u8; RAM_SIZE],
+    ppu_registers: [u8; 8],
+    apu_io_registers: [u8; (APU_IO_REGISTERS_END - APU_IO_REGISTERS + 1) as usize],
+    mapper: M,
+}
+
+impl<M: Mapper> NesBus<M> {
+    pub fn new(mapper: M) -> NesBus<M> {
+        NesBus {
+            open_bus: OpenBus::default(),
+            ram: [0; RAM_SIZE],
+            ppu_registers: [0; 8],
+            apu_io_registers: [0; (APU_IO_REGISTERS_END - APU_IO_REGISTERS + 1) as usize],
+            mapper,
+        }
+    }
+
+    pub fn mapper(&self) -> &M {
+        &self.mapper
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut M {
+        &mut self.mapper
+    }
+
+    /// A write that leaves the open bus alone
+    fn store(&mut self, addr: u16, data: u8) {
+        match addr {
+            0..=RAM_MIRRORS_END => self.ram[addr as usize % RAM_SIZE] = data,
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu_registers[(addr & 0x0007) as usize] = data,
+            APU_IO_REGISTERS..=APU_IO_REGISTERS_END => {
+                self.apu_io_registers[(addr - APU_IO_REGISTERS) as usize] = data
+            }
+            // Only reachable with the test pin pulled high
+            TEST_MODE..=TEST_MODE_END => {}
+            CARTRIDGE..=0xFFFF => self.mapper.write(addr, data),
+        }
+    }
+
+    fn read_apu_io(&self, addr: u16) -> u8 {
+        let data = self.apu_io_registers[(addr - APU_IO_REGISTERS) as usize];
+        match addr {
+            // Bit 5 of the status register is not connected
+            APU_STATUS => self.open_bus.merge(data, 0xDF),
+            // A standard controller drives D0, the expansion port D1-D4
+            JOYPAD1 | JOYPAD2 => self.open_bus.merge(data, 0x1F),
+            // The rest are write only
+            _ => self.open_bus.value(),
+        }
+    }
+}
+
+impl<M: Mapper + Default> Default for NesBus<M> {
+    fn default() -> Self {
+        Self::new(M::default())
+    }
+}
+
+impl<M: Mapper> Bus for NesBus<M> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = match addr {
+            CARTRIDGE..=0xFFFF => self.mapper.read(addr).unwrap_or(self.open_bus.value()),
+            // The stand-in registers have no read side effects
+            _ => self.peek(addr),
+        };
+        // $4015 is read inside the 2A03 and never reaches the external data
+        // bus, so the latch keeps what was there before
+        if addr != APU_STATUS {
+            self.open_bus.latch(data);
+        }
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.open_bus.latch(data);
+        self.store(addr, data);
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0..=RAM_MIRRORS_END => self.ram[addr as usize % RAM_SIZE],
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu_registers[(addr & 0x0007) as usize],
+            APU_IO_REGISTERS..=APU_IO_REGISTERS_END => self.read_apu_io(addr),
+            TEST_MODE..=TEST_MODE_END => self.open_bus.value(),
+            CARTRIDGE..=0xFFFF => self.mapper.peek(addr).unwrap_or(self.open_bus.value()),
+        }
+    }
+
+    fn poke(&mut self, addr: u16, data: u8) {
+        match addr {
+            CARTRIDGE..=0xFFFF => self.mapper.poke(addr, data),
+            _ => self.store(addr, data),
+        }
+    }
+
+    fn open_bus(&self) -> Option<&OpenBus> {
+        Some(&self.open_bus)
+    }
+
+    fn open_bus_mut(&mut self) -> Option<&mut OpenBus> {
+        Some(&mut self.open_bus)
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.ram)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::{Memory, StopReason, CPU};
+    use crate::cpu_types::{CpuVariant, RamInit};
+    use crate::mapper::Nrom;
+
+    /// Flat RAM that counts reads and the cycles it was ticked for
+    #[derive(Default)]
+    struct CountingBus {
+        ram: FlatRam,
+        reads: usize,
+        cycles: u64,
+    }
+
+    impl Bus for CountingBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.reads += 1;
+            self.ram.read(addr)
+        }
+
+        fn write(&mut self, addr: u16, data: u8) {
+            self.ram.write(addr, data);
+        }
+
+        fn peek(&self, addr: u16) -> u8 {
+            self.ram.peek(addr)
+        }
+
+        fn tick(&mut self, cycles: u8) {
+            self.cycles += cycles as u64;
+        }
+
+        fn ram_mut(&mut self) -> Option<&mut [u8]> {
+            self.ram.ram_mut()
+        }
+    }
+
+    #[test]
+    fn test_cpu_on_a_custom_bus() {
+        let mut cpu = CPU::with_bus(CpuVariant::Ricoh2A03, CountingBus::default());
+        // LDA #$05; STA $10; INC $10; NOP
+        cpu.load(vec![0xa9, 0x05, 0x85, 0x10, 0xe6, 0x10, 0xea]);
+        cpu.reset();
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        while cpu.tick().unwrap().is_none() {}
+        assert_eq!(cpu.bus().ram.peek(0x10), 0x06);
+        // The reset sequence, then both paths let the bus catch up with
+        // every cycle
+        assert_eq!(cpu.bus().cycles, 7 + 2 + 3 + 5);
+
+        let reads = cpu.bus().reads;
+        assert_eq!(cpu.peek(0x8006), 0xea);
+        assert_eq!(cpu.bus().reads, reads);
+        assert_eq!(cpu.mem_read(0x8006), 0xea);
+        assert_eq!(cpu.bus().reads, reads + 1);
+    }
+
+    #[test]
+    fn test_step_reads_operands_once() {
+        let mut cpu = CPU::with_bus(CpuVariant::Ricoh2A03, CountingBus::default());
+        // LDA ($10),Y; BNE +0
+        cpu.load(vec![0xb1, 0x10, 0xd0, 0x00]);
+        cpu.reset();
+        cpu.mem_write_u16(0x10, 0x0300);
+        cpu.mem_write(0x0300, 0x01);
+
+        let reads = cpu.bus().reads;
+        let result = cpu.step().unwrap();
+        assert_eq!(result.operand_address, Some(0x0300));
+        // Opcode, operand, both pointer bytes and the data
+        assert_eq!(cpu.bus().reads, reads + 5);
+
+        let reads = cpu.bus().reads;
+        assert_eq!(cpu.step().unwrap().operand_address, Some(0x8004));
+        assert_eq!(cpu.bus().reads, reads + 2);
+    }
+
+    #[test]
+    fn test_stop_on_brk_does_not_read_the_bus() {
+        let mut cpu = CPU::with_bus(CpuVariant::Ricoh2A03, CountingBus::default());
+        // NOP; NOP; BRK
+        cpu.load(vec![0xea, 0xea, 0x00]);
+        cpu.reset();
+        cpu.stop_on_brk = true;
+
+        let reads = cpu.bus().reads;
+        assert_eq!(cpu.run(), Ok(StopReason::Brk { pc: 0x8002 }));
+        // Only the two opcode fetches
+        assert_eq!(cpu.bus().reads, reads + 2);
+    }
+
+    #[test]
+    fn test_every_reset_ticks_the_bus() {
+        let mut cpu = CPU::with_bus(CpuVariant::Ricoh2A03, CountingBus::default());
+        cpu.power_on(RamInit::Zero);
+        assert_eq!(cpu.bus().cycles, 7);
+        cpu.soft_reset();
+        assert_eq!(cpu.bus().cycles, 14);
+        cpu.reset();
+        assert_eq!(cpu.bus().cycles, 21);
+    }
+
+    #[test]
+    fn test_nes_ram_mirrors() {
+        let mut bus = NesBus::new(Nrom::new(vec![0; 0x4000]));
+        bus.write(0x0012, 0x34);
+        assert_eq!(bus.read(0x0812), 0x34);
+        assert_eq!(bus.read(0x1012), 0x34);
+        bus.write(0x1fff, 0x56);
+        assert_eq!(bus.read(0x07ff), 0x56);
+    }
+
+    #[test]
+    fn test_nes_ppu_register_mirrors() {
+        let mut bus = NesBus::new(Nrom::new(vec![0; 0x4000]));
+        bus.write(0x3ff9, 0x1e);
+        assert_eq!(bus.read(0x2001), 0x1e);
+        assert_eq!(bus.read(0x2009), 0x1e);
+        // $4000 is past the mirrors, a write-only APU register
+        bus.write(0x2001, 0x00);
+        assert_eq!(bus.read(0x3ff9), 0x00);
+        bus.write(0x0000, 0x1e);
+        assert_eq!(bus.read(0x4001), 0x1e);
+    }
+
+    #[test]
+    fn test_nes_cartridge_space() {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0x0123] = 0xab;
+        let mut bus = NesBus::new(Nrom::new(prg_rom));
+        assert_eq!(bus.read(0x8123), 0xab);
+        assert_eq!(bus.read(0xc123), 0xab);
+        bus.write(0x8123, 0x00);
+        assert_eq!(bus.read(0x8123), 0xab);
+        bus.write(0x6000, 0x42);
+        assert_eq!(bus.mapper().peek(0x6000), Some(0x42));
+        // The test mode registers are not there on a retail console
+        bus.write(0x401a, 0x99);
+        bus.write(0x0000, 0x00);
+        assert_eq!(bus.read(0x401a), 0x00);
+    }
+
+    #[test]
+    fn test_cpu_on_the_nes_bus() {
+        // LDA #$07; STA $0802; LDX $1002; BRK
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..8].copy_from_slice(&[0xa9, 0x07, 0x8d, 0x02, 0x08, 0xae, 0x02, 0x10]);
+        prg_rom[0x3ffc] = 0x00;
+        prg_rom[0x3ffd] = 0xc0;
+        let mut cpu = CPU::with_bus(CpuVariant::Ricoh2A03, NesBus::new(Nrom::new(prg_rom)));
+        cpu.reset();
+        assert_eq!(cpu.program_counter, 0xc000);
+
+        cpu.run_instructions(3);
+        assert_eq!(cpu.register_x, 0x07);
+        assert_eq!(cpu.mem_read(0x0002), 0x07);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut bus = NesBus::new(Nrom::new(vec![0; 0x4000]));
+        bus.write(0x4016, 0x01);
+        bus.write(0x4015, 0xff);
+        bus.write(0x0000, 0xa0);
+        assert_eq!(bus.read(0x0000), 0xa0);
+        assert_eq!(bus.read(0x4016), 0xa1);
+        assert_eq!(bus.read(0x4015), 0xff);
+        bus.write(0x0000, 0x00);
+        assert_eq!(bus.read(0x0000), 0x00);
+        assert_eq!(bus.read(0x4015), 0xdf);
+        // Write-only APU registers, the test range and empty cartridge space
+        bus.write(0x4000, 0x3f);
+        bus.read(0x0000);
+        assert_eq!(bus.read(0x4000), 0x00);
+        bus.write(0x0001, 0x5a);
+        assert_eq!(bus.read(0x401f), 0x5a);
+        assert_eq!(bus.peek(0x5000), 0x5a);
+    }
+
+    #[test]
+    fn test_apu_status_read_is_not_latched() {
+        let mut bus = NesBus::new(Nrom::new(vec![0; 0x4000]));
+        bus.write(0x4015, 0x0f);
+        bus.write(0x0000, 0x60);
+        // Bit 5 comes off the open bus, which the read leaves as it was
+        assert_eq!(bus.read(0x4015), 0x2f);
+        assert_eq!(bus.read(0x5000), 0x60);
+    }
+
+    #[test]
+    fn test_cpu_reads_open_bus() {
+        // LDA $5000; LDX $4016; BRK
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..6].copy_from_slice(&[0xad, 0x00, 0x50, 0xae, 0x16, 0x40]);
+        prg_rom[0x3ffd] = 0xc0;
+        let mut cpu = CPU::with_bus(CpuVariant::Ricoh2A03, NesBus::new(Nrom::new(prg_rom)));
+        cpu.reset();
+        cpu.run_instructions(2);
+
+        // The high byte of the operand was the last thing on the bus
+        assert_eq!(cpu.register_a, 0x50);
+        assert_eq!(cpu.register_x, 0x40);
+    }
+
+    #[test]
+    fn test_power_on_fills_ram_directly() {
+        let mut cpu = CPU::with_bus(CpuVariant::Ricoh2A03, NesBus::new(Nrom::new(vec![0; 0x4000])));
+        cpu.power_on(RamInit::Ones);
+        assert_eq!(cpu.peek(0x0000), 0xff);
+        assert_eq!(cpu.peek(0x1fff), 0xff);
+        // No emulated writes, so nothing went over the data bus
+        assert_eq!(cpu.bus().open_bus(), Some(&OpenBus::default()));
+    }
+}
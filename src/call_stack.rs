@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+use std::fmt;
+use crate::cpu::StepResult;
+use crate::cpu_types::{Interrupt, Operation};
+
+// Deeper than the hardware stack can go without wrapping around
+const MAX_FRAMES: usize = 256;
+const MAX_DESYNCS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Subroutine,
+    Interrupt(Interrupt),
+}
+
+/// One entry of the shadow call stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// The JSR or BRK, or the instruction an NMI/IRQ preempted
+    pub call_site: u16,
+    /// Routine or interrupt handler that was entered
+    pub target: u16,
+    /// Where the matching RTS/RTI is expected to land
+    pub return_address: u16,
+    /// Stack pointer right after the return address (and status) was pushed
+    pub stack_pointer: u8,
+}
+
+/// Stack manipulation the shadow stack could not follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Desync {
+    /// RTS/RTI at `pc` went to `actual`, which matches no frame. `expected`
+    /// is the return address of the innermost frame, if any.
+    UnexpectedReturn { pc: u16, expected: Option<u16>, actual: u16 },
+    /// `count` frames were left without returning from them, noticed at `pc`
+    AbandonedFrames { pc: u16, count: usize },
+}
+
+/// Shadow of the JSR/RTS and interrupt/RTI nesting, built from executed
+/// instructions rather than from the contents of the stack page
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+    desyncs: VecDeque<Desync>,
+}
+
+impl CallStack {
+    /// Outermost frame first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// The most recent desyncs, oldest first
+    pub fn desyncs(&self) -> impl Iterator<Item = &Desync> {
+        self.desyncs.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.desyncs.clear();
+    }
+
+    /// Follows one executed instruction or serviced interrupt. `stack_pointer`
+    /// is the value after it ran.
+    pub(crate) fn track(&mut self, result: &StepResult, stack_pointer: u8) {
+        if let Some(interrupt) = result.interrupt {
+            self.enter(FrameKind::Interrupt(interrupt), result, result.pc_before, stack_pointer);
+            return;
+        }
+        match result.instruction.operation {
+            Operation::JSR => {
+                self.enter(FrameKind::Subroutine, result, result.pc_before.wrapping_add(3), stack_pointer)
+            }
+            // BRK skips a padding byte
+            Operation::BRK => self.enter(
+                FrameKind::Interrupt(Interrupt::Brk),
+                result,
+                result.pc_before.wrapping_add(2),
+                stack_pointer,
+            ),
+            Operation::RTS | Operation::RTI => self.leave(result.pc_before, result.pc_after),
+            _ => {}
+        }
+    }
+
+    fn enter(&mut self, kind: FrameKind, result: &StepResult, return_address: u16, stack_pointer: u8) {
+        // Frames at or below the new one on the hardware stack were dropped
+        // without returning, e.g. by TXS
+        let live = self.frames.iter().take_while(|frame| frame.stack_pointer > stack_pointer).count();
+        if live < self.frames.len() {
+            self.flag(Desync::AbandonedFrames { pc: result.pc_before, count: self.frames.len() - live });
+            self.frames.truncate(live);
+        }
+        if self.frames.len() == MAX_FRAMES {
+            self.frames.remove(0);
+        }
+        self.frames.push(Frame {
+            kind,
+            call_site: result.pc_before,
+            target: result.pc_after,
+            return_address,
+            stack_pointer,
+        });
+    }
+
+    fn leave(&mut self, pc: u16, return_address: u16) {
+        match self.frames.iter().rposition(|frame| frame.return_address == return_address) {
+            Some(index) => {
+                let abandoned = self.frames.len() - 1 - index;
+                if abandoned > 0 {
+                    self.flag(Desync::AbandonedFrames { pc, count: abandoned });
+                }
+                self.frames.truncate(index);
+            }
+            // Likely an RTS used as a computed jump; the frames stay
+            None => self.flag(Desync::UnexpectedReturn {
+                pc,
+                expected: self.frames.last().map(|frame| frame.return_address),
+                actual: return_address,
+            }),
+        }
+    }
+
+    fn flag(&mut self, desync: Desync) {
+        if self.desyncs.len() == MAX_DESYNCS {
+            self.desyncs.pop_front();
+        }
+        self.desyncs.push_back(desync);
+    }
+}
+
+/// Innermost frame first, one per line
+impl fmt::Display for CallStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            let kind = match frame.kind {
+                FrameKind::Subroutine => "JSR",
+                FrameKind::Interrupt(Interrupt::Brk) => "BRK",
+                FrameKind::Interrupt(Interrupt::Irq) => "IRQ",
+                FrameKind::Interrupt(Interrupt::Nmi) => "NMI",
+            };
+            writeln!(
+                f,
+                "#{} {} ${:04X} from ${:04X}, returns to ${:04X}",
+                depth, kind, frame.target, frame.call_site, frame.return_address
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::cpu_types::NMI_VECTOR;
+
+    fn cpu_with_program(program: Vec<u8>) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load(program);
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        // JSR $8006; BRK; NOP; NOP; $8006: JSR $800A; RTS; $800A: NOP; RTS
+        let mut cpu = cpu_with_program(vec![
+            0x20, 0x06, 0x80, 0x00, 0xea, 0xea, 0x20, 0x0a, 0x80, 0x60, 0xea, 0x60,
+        ]);
+        cpu.run_until_pc(0x800a);
+
+        let frames = cpu.backtrace().frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].call_site, 0x8000);
+        assert_eq!(frames[0].target, 0x8006);
+        assert_eq!(frames[1].return_address, 0x8009);
+        assert_eq!(
+            cpu.backtrace().to_string(),
+            "#0 JSR $800A from $8006, returns to $8009\n#1 JSR $8006 from $8000, returns to $8003\n"
+        );
+
+        cpu.run_until_pc(0x8003);
+        assert!(cpu.backtrace().frames().is_empty());
+        assert_eq!(cpu.backtrace().desyncs().count(), 0);
+    }
+
+    #[test]
+    fn test_interrupt_frames_with_tick() {
+        let mut cpu = cpu_with_program(vec![0xea, 0xea]);
+        cpu.poke_u16(NMI_VECTOR, 0x9000);
+        cpu.poke(0x9000, 0x40); // RTI
+        cpu.trigger_nmi();
+
+        while cpu.tick().unwrap().is_none() {}
+        let frame = cpu.backtrace().frames()[0];
+        assert_eq!(frame.kind, FrameKind::Interrupt(Interrupt::Nmi));
+        assert_eq!(frame.return_address, 0x8000);
+
+        while cpu.tick().unwrap().is_none() {}
+        assert!(cpu.backtrace().frames().is_empty());
+    }
+
+    #[test]
+    fn test_desyncs_are_flagged() {
+        // JSR $8005; BRK; NOP; $8005: LDX #$FF; TXS; JSR $800D; BRK
+        // $800D: LDA #$80; PHA; LDA #$13; PHA; RTS (computed jump to $8014)
+        // $8014: NOP
+        let mut cpu = cpu_with_program(vec![
+            0x20, 0x05, 0x80, 0x00, 0xea, 0xa2, 0xff, 0x9a, 0x20, 0x0d, 0x80, 0x00, 0x00, 0xa9,
+            0x80, 0x48, 0xa9, 0x13, 0x48, 0x60, 0xea,
+        ]);
+        cpu.run_until_pc(0x8014);
+
+        // Resetting the stack abandoned the outer frame, the RTS matched nothing
+        let desyncs: Vec<Desync> = cpu.backtrace().desyncs().copied().collect();
+        assert_eq!(
+            desyncs,
+            vec![
+                Desync::AbandonedFrames { pc: 0x8008, count: 1 },
+                Desync::UnexpectedReturn { pc: 0x8013, expected: Some(0x800b), actual: 0x8014 },
+            ]
+        );
+        assert_eq!(cpu.backtrace().frames().len(), 1);
+        assert_eq!(cpu.backtrace().frames()[0].target, 0x800d);
+    }
+}
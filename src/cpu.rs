@@ -1,48 +1,273 @@
-use std::collections::HashMap;
-use crate::cpu_types::{AddressingMode, Operation, CpuFlag, STACK_RESET, STACK};
-use crate::instruction::{Instruction, INSTRUCTIONS_MAP};
-
-pub struct CPU {
+use std::ops::ControlFlow;
+use crate::bus::{Bus, NesBus};
+use crate::mapper::Nrom;
+use crate::call_stack::CallStack;
+use crate::cpu_error::CpuError;
+use crate::cpu_state::CpuState;
+use crate::cpu_types::{
+    AddressingMode, Operation, CpuFlag, CpuVariant, Interrupt, IrqSource, RamInit, UnofficialOpcodePolicy, STACK_RESET, STACK,
+    NMI_VECTOR, RESET_VECTOR,
+};
+use crate::instruction::{decode_table, DecodeTable, Instruction};
+
+mod cycle;
+
+/// The 6502 core, on any `Bus`. By default it sits on the 2A03 memory map
+/// with a blank NROM cartridge, which `load` writes programs into.
+pub struct CPU<B: Bus = NesBus<Nrom>> {
     pub register_a: u8,
     pub register_x: u8,
     pub register_y: u8,
     pub stack_pointer: u8,
     pub status: CpuFlag,
     pub program_counter: u16,
-    memory: [u8; 0xFFFF],
+    /// Total CPU cycles elapsed since power-on or the last `reset`
+    pub cycles: u64,
+    /// When set, `run` returns as soon as it reaches a BRK instead of
+    /// servicing it through the IRQ vector
+    pub stop_on_brk: bool,
+    pub unofficial_opcodes: UnofficialOpcodePolicy,
+    variant: CpuVariant,
+    nmi_pending: bool,
+    irq_sources: IrqSource,
+    /// What `tick` saw when it polled the interrupt lines on the second to
+    /// last cycle of the previous instruction. `None` after a `step`, which
+    /// checks the lines as they are at the instruction boundary.
+    interrupt_polled: Option<bool>,
+    /// The interrupt disable flag as the previous instruction polled it,
+    /// when CLI/SEI/PLP changed it on their last cycle
+    masked_at_poll: Option<bool>,
+    micro: cycle::MicroState,
+    call_stack: CallStack,
+    bus: B,
+}
+
+fn page_cross(addr1: u16, addr2: u16) -> bool {
+    addr1 & 0xFF00 != addr2 & 0xFF00
+}
+
+/// The bit RMB/SMB/BBR/BBS work on, encoded in bits 4-6 of their opcode
+fn bit_mask(code: u8) -> u8 {
+    1 << ((code >> 4) & 0x07)
+}
+
+/// SHA/SHX/SHY/TAS store `value & (H + 1)`, H being the high byte of the
+/// unindexed base address. When indexing crosses a page the stored value also
+/// replaces the high byte of the target address.
+fn high_and_store(addr: u16, index: u8, value: u8, page_cross: bool) -> (u16, u8) {
+    let base = addr.wrapping_sub(index as u16);
+    let data = value & ((base >> 8) as u8).wrapping_add(1);
+    if page_cross {
+        ((data as u16) << 8 | (addr & 0x00FF), data)
+    } else {
+        (addr, data)
+    }
+}
+
+fn read_u16<F: FnMut(u16) -> u8>(read: &mut F, addr: u16) -> u16 {
+    u16::from_le_bytes([read(addr), read(addr.wrapping_add(1))])
+}
+
+/// Resolves the effective address of the operand at `pc` along with whether
+/// indexing crossed a page boundary, fetching bytes through `read`
+fn resolve_operand_address<F>(mode: &AddressingMode, pc: u16, x: u8, y: u8, cmos: bool, mut read: F) -> Result<(u16, bool), CpuError>
+where
+    F: FnMut(u16) -> u8,
+{
+    let addr = match mode {
+        AddressingMode::Immediate => pc,
+        AddressingMode::ZeroPage | AddressingMode::ZeroPageRelative => read(pc) as u16,
+        AddressingMode::ZeroPageX => {
+            let pos = read(pc);
+            pos.wrapping_add(x) as u16
+        }
+        AddressingMode::ZeroPageY => {
+            let pos = read(pc);
+            pos.wrapping_add(y) as u16
+        }
+        AddressingMode::Absolute => read_u16(&mut read, pc),
+        AddressingMode::AbsoluteX => {
+            let base = read_u16(&mut read, pc);
+            let addr = base.wrapping_add(x as u16);
+            return Ok((addr, page_cross(base, addr)));
+        }
+        AddressingMode::Indirect => {
+            let base = read_u16(&mut read, pc);
+            // The NMOS parts never carry into the high byte of the pointer
+            if base & 0x00FF == 0x00FF && !cmos {
+                let lo = read(base);
+                let hi = read(base & 0xFF00);
+                (hi as u16) << 8 | (lo as u16)
+            } else {
+                read_u16(&mut read, base)
+            }
+        }
+        AddressingMode::AbsoluteY => {
+            let base = read_u16(&mut read, pc);
+            let addr = base.wrapping_add(y as u16);
+            return Ok((addr, page_cross(base, addr)));
+        }
+        AddressingMode::IndirectX => {
+            let base = read(pc);
+            let ptr: u8 = base.wrapping_add(x);
+            let lo = read(ptr as u16);
+            let hi = read(ptr.wrapping_add(1) as u16);
+            (hi as u16) << 8 | (lo as u16)
+        }
+        AddressingMode::IndirectY => {
+            let base = read(pc);
+            let lo = read(base as u16);
+            let hi = read(base.wrapping_add(1) as u16);
+            let deref_base = (hi as u16) << 8 | (lo as u16);
+            let addr = deref_base.wrapping_add(y as u16);
+            return Ok((addr, page_cross(deref_base, addr)));
+        }
+        AddressingMode::ZeroPageIndirect => {
+            let ptr = read(pc);
+            let lo = read(ptr as u16);
+            let hi = read(ptr.wrapping_add(1) as u16);
+            (hi as u16) << 8 | (lo as u16)
+        }
+        AddressingMode::AbsoluteIndexedIndirect => {
+            let base = read_u16(&mut read, pc);
+            read_u16(&mut read, base.wrapping_add(x as u16))
+        }
+        AddressingMode::Implied => 0,
+        AddressingMode::Relative => 0,
+        AddressingMode::Accumulator => 0,
+        _ => {
+            return Err(CpuError::UnsupportedAddressingMode {
+                pc: pc.wrapping_sub(1),
+                mode: *mode,
+            });
+        }
+    };
+    Ok((addr, false))
+}
+
+/// What a single call to `CPU::step` did
+#[derive(Debug, Clone, Copy)]
+pub struct StepResult {
+    pub opcode: u8,
+    pub instruction: &'static Instruction,
+    /// Effective address of the operand, or the branch target for branches.
+    /// `None` for implied and accumulator instructions.
+    pub operand_address: Option<u16>,
+    /// Cycles taken, including page-cross and branch penalties
+    pub cycles: u8,
+    pub pc_before: u16,
+    pub pc_after: u16,
+    /// Set when this step serviced a hardware interrupt instead of running an
+    /// instruction. `opcode` and `instruction` then describe the BRK sequence
+    /// the CPU forces into its instruction register.
+    pub interrupt: Option<Interrupt>,
+    /// `CpuError::UnofficialOpcode` when the instruction is undocumented and
+    /// the policy is `UnofficialOpcodePolicy::Warn`. The instruction still ran.
+    pub warning: Option<CpuError>,
+}
+
+impl StepResult {
+    /// True when a JMP or a branch on the flags jumped to itself, or a 65C02
+    /// WAI is still waiting. The CPU then idles until an interrupt arrives.
+    /// `JSR *` and a BRK vectored at itself also come back to the same
+    /// address, but push to the stack every time round, so they don't count.
+    pub fn is_idle_loop(&self) -> bool {
+        let idles = matches!(
+            self.instruction.operation,
+            Operation::JMP | Operation::BCC | Operation::BCS | Operation::BEQ | Operation::BMI
+                | Operation::BNE | Operation::BPL | Operation::BVC | Operation::BVS | Operation::BRA
+                | Operation::WAI
+        );
+        idles && self.interrupt.is_none() && self.pc_after == self.pc_before
+    }
+}
+
+/// Why a run returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The cycle or instruction budget ran out
+    BudgetExhausted,
+    /// The target address or stop condition was reached, before executing
+    /// the instruction at `pc`
+    Breakpoint { pc: u16 },
+    /// `stop_on_brk` is set and the next instruction is a BRK
+    Brk { pc: u16 },
+    /// The CPU hit a JAM opcode, or the 65C02 STP, and halted
+    Jam { pc: u16, opcode: u8 },
+    /// The instruction at `pc` jumps to itself; nothing changes until an
+    /// interrupt, so a frontend can skip ahead to the next one
+    Idle { pc: u16 },
+    Error(CpuError),
+}
+
+impl From<CpuError> for StopReason {
+    fn from(error: CpuError) -> Self {
+        match error {
+            CpuError::Jammed { pc, opcode } => StopReason::Jam { pc, opcode },
+            error => StopReason::Error(error),
+        }
+    }
 }
 
-trait Memory {
-    fn mem_read(&self, addr: u16) -> u8;
+/// Byte and word access on top of a `Bus`
+pub trait Memory {
+    fn mem_read(&mut self, addr: u16) -> u8;
 
     fn mem_write(&mut self, addr: u16, data: u8);
 
-    fn mem_read_u16(&self, pos: u16) -> u16 {
+    /// Stores `data` at `addr` for a loader, ROM included. `false` if
+    /// nothing there holds on to the byte.
+    fn mem_load(&mut self, addr: u16, data: u8) -> bool;
+
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
         let lo = self.mem_read(pos);
-        let hi = self.mem_read(pos + 1);
+        let hi = self.mem_read(pos.wrapping_add(1));
         u16::from_le_bytes([lo, hi])
     }
 
     fn mem_write_u16(&mut self, pos: u16, data: u16) {
         let bytes = data.to_le_bytes();
         self.mem_write(pos, bytes[0]);
-        self.mem_write(pos + 1, bytes[1]);
+        self.mem_write(pos.wrapping_add(1), bytes[1]);
     }
 }
 
-impl Memory for CPU {
-    fn mem_read(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
+impl<B: Bus> Memory for CPU<B> {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.bus.read(addr)
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
-        self.memory[addr as usize] = data;
+        self.bus.write(addr, data);
+    }
+
+    fn mem_load(&mut self, addr: u16, data: u8) -> bool {
+        self.bus.poke(addr, data);
+        self.bus.peek(addr) == data
     }
 }
 
-impl CPU {
-    //noinspection RsTypeCheck
+impl<B: Bus + Default> Default for CPU<B> {
+    fn default() -> Self {
+        Self::with_bus(CpuVariant::default(), B::default())
+    }
+}
+
+impl CPU<NesBus<Nrom>> {
+    /// Creates a Ricoh 2A03, the NES CPU, on the NES memory map with a blank
+    /// 32KB NROM cartridge
     pub fn new() -> CPU {
+        CPU::with_variant(CpuVariant::Ricoh2A03)
+    }
+
+    pub fn with_variant(variant: CpuVariant) -> CPU {
+        CPU::with_bus(variant, NesBus::default())
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    //noinspection RsTypeCheck
+    pub fn with_bus(variant: CpuVariant, bus: B) -> CPU<B> {
         CPU {
             register_a: 0,
             register_x: 0,
@@ -50,63 +275,71 @@ impl CPU {
             stack_pointer: 0,
             status: CpuFlag::empty(),
             program_counter: 0,
-            memory: [0; 0xFFFF]
+            cycles: 0,
+            stop_on_brk: false,
+            unofficial_opcodes: UnofficialOpcodePolicy::default(),
+            variant,
+            nmi_pending: false,
+            irq_sources: IrqSource::empty(),
+            interrupt_polled: None,
+            masked_at_poll: None,
+            micro: cycle::MicroState::default(),
+            call_stack: CallStack::default(),
+            bus,
         }
     }
 
-    fn get_operand_address(&self, mode: &AddressingMode) -> u16 {
-        match mode {
-            AddressingMode::Immediate => self.program_counter,
-            AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
-            AddressingMode::ZeroPageX => {
-                let pos = self.mem_read(self.program_counter);
-                pos.wrapping_add(self.register_x) as u16
-            }
-            AddressingMode::ZeroPageY => {
-                let pos = self.mem_read(self.program_counter);
-                pos.wrapping_add(self.register_y) as u16
-            }
-            AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
-            AddressingMode::AbsoluteX => {
-                let base = self.mem_read_u16(self.program_counter);
-                base.wrapping_add(self.register_x as u16)
-            }
-            AddressingMode::Indirect => {
-                let base = self.mem_read_u16(self.program_counter);
-                let indirect_ref = if base & 0x00FF == 0x00FF {
-                    let lo = self.mem_read(base);
-                    let hi = self.mem_read(base & 0xFF00);
-                    (hi as u16) << 8 | (lo as u16)
-                } else {
-                    self.mem_read_u16(base)
-                };
-                indirect_ref
-            }
-            AddressingMode::AbsoluteY => {
-                let base = self.mem_read_u16(self.program_counter);
-                base.wrapping_add(self.register_y as u16)
-            }
-            AddressingMode::IndirectX => {
-                let base = self.mem_read(self.program_counter);
-                let ptr: u8 = base.wrapping_add(self.register_x);
-                let lo = self.mem_read(ptr as u16);
-                let hi = self.mem_read(ptr.wrapping_add(1) as u16);
-                (hi as u16) << 8 | (lo as u16)
-            }
-            AddressingMode::IndirectY => {
-                let base = self.mem_read(self.program_counter);
-                let lo = self.mem_read(base as u16);
-                let hi = self.mem_read(base.wrapping_add(1) as u16);
-                let deref_base = (hi as u16) << 8 | (lo as u16);
-                deref_base.wrapping_add(self.register_y as u16)
-            }
-            AddressingMode::Implied => 0,
-            AddressingMode::Relative => 0,
-            AddressingMode::Accumulator => 0,
-            _ => {
-                panic!("Mode not supported");
-            }
-        }
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Reads `addr` without side effects on the bus
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
+    }
+
+    /// Stores `data` at `addr` without side effects on the bus, into ROM
+    /// too where the bus allows it
+    pub fn poke(&mut self, addr: u16, data: u8) {
+        self.bus.poke(addr, data);
+    }
+
+    pub fn peek_u16(&self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.peek(addr), self.peek(addr.wrapping_add(1))])
+    }
+
+    pub fn poke_u16(&mut self, addr: u16, data: u16) {
+        let bytes = data.to_le_bytes();
+        self.poke(addr, bytes[0]);
+        self.poke(addr.wrapping_add(1), bytes[1]);
+    }
+
+    pub fn variant(&self) -> CpuVariant {
+        self.variant
+    }
+
+    /// Resolves the effective address of the operand along with whether
+    /// indexing crossed a page boundary
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> Result<(u16, bool), CpuError> {
+        let (pc, x, y, cmos) = (self.program_counter, self.register_x, self.register_y, self.variant.is_cmos());
+        resolve_operand_address(mode, pc, x, y, cmos, |addr| self.mem_read(addr))
+    }
+
+    /// Where the branch with its offset at `offset_addr` goes if taken,
+    /// without touching the bus
+    fn peek_branch_target(&self, offset_addr: u16) -> u16 {
+        let jump = self.peek(offset_addr) as i8;
+        offset_addr.wrapping_add(1).wrapping_add(jump as u16)
+    }
+
+    /// `get_operand_address` without touching the bus, for reporting
+    fn peek_operand_address(&self, mode: &AddressingMode) -> Result<(u16, bool), CpuError> {
+        let (pc, x, y, cmos) = (self.program_counter, self.register_x, self.register_y, self.variant.is_cmos());
+        resolve_operand_address(mode, pc, x, y, cmos, |addr| self.peek(addr))
     }
 
     fn stack_pop(&mut self) -> u8 {
@@ -132,7 +365,7 @@ impl CPU {
         hi << 8 | lo
     }
 
-    // Ignoring decimal mode
+    // Binary addition only, the 2A03 has no decimal mode
     fn add_to_register_a(&mut self, data: u8) {
         let mut sum = self.register_a as u16 + data as u16;
 
@@ -160,21 +393,162 @@ impl CPU {
         self.register_a = result;
     }
 
-    fn adc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-        self.add_to_register_a(value);
+    fn decimal_mode(&self) -> bool {
+        self.variant.has_decimal_mode() && self.status.contains(CpuFlag::DECIMAL)
+    }
+
+    /// ADC, honouring the D flag on variants that have decimal mode
+    fn add_with_carry(&mut self, data: u8) {
+        if self.decimal_mode() {
+            self.add_decimal_to_register_a(data);
+            self.fix_cmos_decimal_flags();
+        } else {
+            self.add_to_register_a(data);
+            self.update_zero_and_negative_flags(self.register_a);
+        }
+    }
+
+    /// SBC, honouring the D flag on variants that have decimal mode
+    fn subtract_with_borrow(&mut self, data: u8) {
+        if self.decimal_mode() {
+            self.subtract_decimal_from_register_a(data);
+            self.fix_cmos_decimal_flags();
+        } else {
+            // A - M - (1 - C) is the same as A + !M + C
+            self.add_to_register_a(!data);
+            self.update_zero_and_negative_flags(self.register_a);
+        }
+    }
+
+    /// NMOS decimal ADC. A and C hold the BCD result, but N and V come from
+    /// the sum before the high nibble is adjusted and Z from the binary sum.
+    /// http://www.6502.org/tutorials/decimal_mode.html#A
+    fn add_decimal_to_register_a(&mut self, data: u8) {
+        let a = self.register_a;
+        let carry = self.status.contains(CpuFlag::CARRY) as u16;
+        let binary = (a as u16 + data as u16 + carry) as u8;
+
+        let mut lo = (a & 0x0F) as u16 + (data & 0x0F) as u16 + carry;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (a & 0xF0) as u16 + (data & 0xF0) as u16 + lo;
+        let signed_sum = (a & 0xF0) as i8 as i16 + (data & 0xF0) as i8 as i16 + lo as i16;
+
+        self.status.set(CpuFlag::NEGATIVE, sum & 0x80 != 0);
+        self.status.set(CpuFlag::OVERFLOW, !(-128..=127).contains(&signed_sum));
+        self.status.set(CpuFlag::ZERO, binary == 0);
+
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.status.set(CpuFlag::CARRY, sum >= 0x100);
+        self.register_a = sum as u8;
+    }
+
+    /// NMOS decimal SBC. All flags are the same as for binary SBC, only the
+    /// accumulator gets the BCD result.
+    fn subtract_decimal_from_register_a(&mut self, data: u8) {
+        let a = self.register_a;
+        let borrow = !self.status.contains(CpuFlag::CARRY) as i16;
+
+        self.add_to_register_a(!data);
         self.update_zero_and_negative_flags(self.register_a);
+
+        let mut lo = (a & 0x0F) as i16 - (data & 0x0F) as i16 - borrow;
+        let difference = if self.variant.is_cmos() {
+            let mut difference = a as i16 - data as i16 - borrow;
+            if difference < 0 {
+                difference -= 0x60;
+            }
+            if lo < 0 {
+                difference -= 0x06;
+            }
+            difference
+        } else {
+            if lo < 0 {
+                lo = ((lo - 0x06) & 0x0F) - 0x10;
+            }
+            let mut difference = (a & 0xF0) as i16 - (data & 0xF0) as i16 + lo;
+            if difference < 0 {
+                difference -= 0x60;
+            }
+            difference
+        };
+        self.register_a = difference as u8;
+    }
+
+    /// The 65C02 makes N and Z reflect the BCD result, at the cost of an
+    /// extra cycle (see `cmos_decimal_penalty`)
+    fn fix_cmos_decimal_flags(&mut self) {
+        if self.variant.is_cmos() {
+            self.update_zero_and_negative_flags(self.register_a);
+        }
+    }
+
+    fn cmos_decimal_penalty(&self) -> bool {
+        self.variant.is_cmos() && self.decimal_mode()
+    }
+
+    /// The 65C02 only spends the index fix-up cycle of a shift or rotate
+    /// when indexing crosses a page
+    fn shift_operand_address(&mut self, mode: &AddressingMode) -> Result<u16, CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross && self.variant.is_cmos() {
+            self.cycles += 1;
+        }
+        Ok(addr)
+    }
+
+    // Shifts and rotates only set C here, callers update N and Z
+
+    fn shift_left(&mut self, data: u8) -> u8 {
+        self.status.set(CpuFlag::CARRY, data >> 7 == 1);
+        data << 1
+    }
+
+    fn shift_right(&mut self, data: u8) -> u8 {
+        self.status.set(CpuFlag::CARRY, data & 1 == 1);
+        data >> 1
+    }
+
+    fn rotate_left(&mut self, data: u8) -> u8 {
+        let old_carry = self.status.contains(CpuFlag::CARRY);
+        self.status.set(CpuFlag::CARRY, data >> 7 == 1);
+        (data << 1) | old_carry as u8
+    }
+
+    fn rotate_right(&mut self, data: u8) -> u8 {
+        let old_carry = self.status.contains(CpuFlag::CARRY);
+        self.status.set(CpuFlag::CARRY, data & 1 == 1);
+        (data >> 1) | (old_carry as u8) << 7
+    }
+
+    fn adc(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
+        if self.cmos_decimal_penalty() {
+            self.cycles += 1;
+        }
+        let value = self.mem_read(addr);
+        self.add_with_carry(value);
+        Ok(())
     }
 
-    fn and(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn and(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
         let value = self.mem_read(addr);
         self.register_a &= value;
         self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
     }
 
-    fn asl(&mut self, mode: &AddressingMode) -> u8 {
+    fn asl(&mut self, mode: &AddressingMode) -> Result<u8, CpuError> {
         let mut data;
         let addr;
 
@@ -185,46 +559,61 @@ impl CPU {
                 addr = 0;
             }
             _ => {
-                addr = self.get_operand_address(mode);
+                addr = self.shift_operand_address(mode)?;
                 data = self.mem_read(addr);
             }
         }
 
-        if data >> 7 == 1 {
-            self.status.insert(CpuFlag::CARRY);
-        } else {
-            self.status.remove(CpuFlag::CARRY);
-        }
-
-        data <<= 1;
+        data = self.shift_left(data);
 
         match mode {
             AddressingMode::Accumulator => {
                 self.register_a = data;
-                self.register_a
+                self.update_zero_and_negative_flags(data);
+                Ok(data)
             }
             _ => {
                 self.mem_write(addr, data);
                 self.update_zero_and_negative_flags(data);
-                data
+                Ok(data)
             }
         }
     }
 
-    fn branch(&mut self, condition: bool) {
+    /// Takes the branch whose offset is at `offset_addr` if `condition` holds
+    fn branch(&mut self, offset_addr: u16, condition: bool) {
         if condition {
-            let jump: i8 = self.mem_read(self.program_counter) as i8;
-            let jump_addr = self.program_counter
-                .wrapping_add(1)
-                .wrapping_add(jump as u16);
+            let jump: i8 = self.mem_read(offset_addr) as i8;
+            let next_addr = offset_addr.wrapping_add(1);
+            let jump_addr = next_addr.wrapping_add(jump as u16);
+
+            // +1 if branch succeeds, +2 if to a new page
+            self.cycles += 1;
+            if page_cross(next_addr, jump_addr) {
+                self.cycles += 1;
+            }
+
             self.program_counter = jump_addr;
         }
     }
 
-    fn bit(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn brk(&mut self) {
+        // BRK skips a padding byte, so the return address is PC + 2
+        self.program_counter = self.program_counter.wrapping_add(1);
+        self.interrupt(Interrupt::Brk);
+    }
+
+    fn bit(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
         let data = self.mem_read(addr);
+        self.test_bits(data, *mode == AddressingMode::Immediate);
+        Ok(())
+    }
 
+    fn test_bits(&mut self, data: u8, immediate: bool) {
         let and = self.register_a & data;
         if and == 0 {
             self.status.insert(CpuFlag::ZERO);
@@ -232,14 +621,26 @@ impl CPU {
             self.status.remove(CpuFlag::ZERO);
         }
 
+        // 65C02 BIT #imm only touches Z
+        if immediate {
+            return;
+        }
+
         self.status.set(CpuFlag::NEGATIVE, data & 0b10000000 > 0);
         self.status.set(CpuFlag::OVERFLOW, data & 0b01000000 > 0);
     }
 
-    fn compare(&mut self, mode: &AddressingMode, compare_with: u8) {
-        let addr = self.get_operand_address(mode);
+    fn compare(&mut self, mode: &AddressingMode, compare_with: u8) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
         let data = self.mem_read(addr);
+        self.compare_value(data, compare_with);
+        Ok(())
+    }
 
+    fn compare_value(&mut self, data: u8, compare_with: u8) {
         if data <= compare_with {
             self.status.insert(CpuFlag::CARRY);
         } else {
@@ -249,13 +650,18 @@ impl CPU {
         self.update_zero_and_negative_flags(compare_with.wrapping_sub(data));
     }
 
-    fn dec(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
+    fn dec(&mut self, mode: &AddressingMode) -> Result<u8, CpuError> {
+        if *mode == AddressingMode::Accumulator {
+            self.register_a = self.register_a.wrapping_sub(1);
+            self.update_zero_and_negative_flags(self.register_a);
+            return Ok(self.register_a);
+        }
+        let (addr, _) = self.get_operand_address(mode)?;
         let mut data = self.mem_read(addr);
         data = data.wrapping_sub(1);
         self.mem_write(addr, data);
         self.update_zero_and_negative_flags(data);
-        data
+        Ok(data)
     }
 
     fn dex(&mut self) {
@@ -268,19 +674,29 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_y);
     }
 
-    fn eor(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn eor(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
         let data = self.mem_read(addr);
-        self.register_a = data ^ self.register_a;
+        self.register_a ^= data;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
     }
 
-    fn inc(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
+    fn inc(&mut self, mode: &AddressingMode) -> Result<u8, CpuError> {
+        if *mode == AddressingMode::Accumulator {
+            self.register_a = self.register_a.wrapping_add(1);
+            self.update_zero_and_negative_flags(self.register_a);
+            return Ok(self.register_a);
+        }
+        let (addr, _) = self.get_operand_address(mode)?;
         let mut data = self.mem_read(addr);
         data = data.wrapping_add(1);
         self.mem_write(addr, data);
         self.update_zero_and_negative_flags(data);
-        data
+        Ok(data)
     }
 
     fn inx(&mut self) {
@@ -293,36 +709,49 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_y);
     }
 
-    fn jmp(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn jmp(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
         self.program_counter = addr;
+        Ok(())
     }
 
     fn jsr(&mut self) {
-        self.stack_push_u16(self.program_counter + 2 - 1);
+        self.stack_push_u16(self.program_counter.wrapping_add(1));
         let target_addr = self.mem_read_u16(self.program_counter);
         self.program_counter = target_addr;
     }
 
-    fn lda(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn lda(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
         let value = self.mem_read(addr);
         self.register_a = value;
         self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
     }
 
-    fn ldx(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn ldx(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
         let value = self.mem_read(addr);
         self.register_x = value;
         self.update_zero_and_negative_flags(self.register_x);
+        Ok(())
     }
 
-    fn ldy(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn ldy(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
         let value = self.mem_read(addr);
         self.register_y = value;
         self.update_zero_and_negative_flags(self.register_y);
+        Ok(())
     }
 
     fn tax(&mut self) {
@@ -330,7 +759,7 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_x);
     }
 
-    fn lsr(&mut self, mode: &AddressingMode) -> u8 {
+    fn lsr(&mut self, mode: &AddressingMode) -> Result<u8, CpuError> {
         let mut data;
         let addr;
 
@@ -340,202 +769,1818 @@ impl CPU {
                 addr = 0;
             }
             _ => {
-                addr = self.get_operand_address(mode);
+                addr = self.shift_operand_address(mode)?;
                 data = self.mem_read(addr);
             }
         }
 
-        if data & 1 == 1 {
-            self.status.insert(CpuFlag::CARRY);
-        } else {
-            self.status.remove(CpuFlag::CARRY);
-        }
-
-        data >>= 1;
+        data = self.shift_right(data);
 
         match mode {
             AddressingMode::Accumulator => {
                 self.register_a = data;
-                self.register_a
+                self.update_zero_and_negative_flags(data);
+                Ok(data)
             }
             _ => {
                 self.mem_write(addr, data);
                 self.update_zero_and_negative_flags(data);
-                data
+                Ok(data)
             }
         }
     }
 
-    fn ora(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn ora(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
         let data = self.mem_read(addr);
-        self.register_a = data | self.register_a;
+        self.register_a |= data;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
     }
 
-    pub fn reset(&mut self) {
-        self.register_a = 0;
-        self.register_x = 0;
-        self.register_y = 0;
-        self.stack_pointer = STACK_RESET;
-        self.status = CpuFlag::from_bits_truncate(0b00100100);
-        self.program_counter = self.mem_read_u16(0xFFFC);
+    fn pha(&mut self) {
+        self.stack_push(self.register_a);
     }
 
-    pub fn load(&mut self, program: Vec<u8>) {
-        self.memory[0x8000..(0x8000 + program.len())].copy_from_slice(&program);
-        self.mem_write_u16(0xFFFC, 0x8000);
+    fn php(&mut self) {
+        // PHP always pushes the status with the Break and unused bits set
+        self.stack_push(self.status_with_break(true));
     }
 
-    pub fn load_and_run(&mut self, program: Vec<u8>) {
-        self.load(program);
-        self.reset();
-        self.run();
+    /// The B flag only exists on the stack: set by PHP and BRK, clear for
+    /// NMI/IRQ. The unused bit always reads back as set.
+    fn status_with_break(&self, brk: bool) -> u8 {
+        let mut flags = self.status;
+        flags.set(CpuFlag::BREAK, brk);
+        flags.insert(CpuFlag::NULL);
+        flags.bits()
     }
-    fn update_zero_and_negative_flags(&mut self, result: u8) {
-        if result == 0 {
-            self.status.insert(CpuFlag::ZERO);
-        } else {
-            self.status.remove(CpuFlag::ZERO);
+
+    fn pla(&mut self) {
+        self.register_a = self.stack_pop();
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn plp(&mut self) {
+        self.status = CpuFlag::from_bits_truncate(self.stack_pop());
+        self.status.remove(CpuFlag::BREAK);
+        self.status.insert(CpuFlag::NULL);
+    }
+
+    fn rol(&mut self, mode: &AddressingMode) -> Result<u8, CpuError> {
+        let mut data;
+        let addr;
+
+        match mode {
+            AddressingMode::Accumulator => {
+                data = self.register_a;
+                addr = 0;
+            }
+            _ => {
+                addr = self.shift_operand_address(mode)?;
+                data = self.mem_read(addr);
+            }
         }
 
-        if result & 0b1000_0000 != 0 {
-            self.status.insert(CpuFlag::NEGATIVE);
-        } else {
-            self.status.remove(CpuFlag::NEGATIVE);
+        data = self.rotate_left(data);
+
+        match mode {
+            AddressingMode::Accumulator => {
+                self.register_a = data;
+                self.update_zero_and_negative_flags(data);
+                Ok(data)
+            }
+            _ => {
+                self.mem_write(addr, data);
+                self.update_zero_and_negative_flags(data);
+                Ok(data)
+            }
         }
     }
 
-    pub fn run(&mut self) {
-        let ref opcodes: HashMap<u8, &'static Instruction>
-            = *INSTRUCTIONS_MAP;
+    fn ror(&mut self, mode: &AddressingMode) -> Result<u8, CpuError> {
+        let mut data;
+        let addr;
 
-        loop {
-            let code = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-            let program_counter_state = self.program_counter;
-
-            let opcode = opcodes.get(&code)
-                .expect(&format!("Opcode {:x} is not recognized", code));
-
-            match opcode.operation {
-                Operation::ADC => self.adc(&opcode.mode),
-                Operation::AND => self.and(&opcode.mode),
-                Operation::ASL => { self.asl(&opcode.mode); }
-                Operation::BCC => self.branch(!self.status.contains(CpuFlag::CARRY)),
-                Operation::BCS => self.branch(self.status.contains(CpuFlag::CARRY)),
-                Operation::BEQ => self.branch(self.status.contains(CpuFlag::ZERO)),
-                Operation::BIT => self.bit(&opcode.mode),
-                Operation::BMI => self.branch(self.status.contains(CpuFlag::NEGATIVE)),
-                Operation::BNE => self.branch(!self.status.contains(CpuFlag::ZERO)),
-                Operation::BPL => self.branch(!self.status.contains(CpuFlag::NEGATIVE)),
-                Operation::BRK => return,
-                Operation::BVC => self.branch(!self.status.contains(CpuFlag::OVERFLOW)),
-                Operation::BVS => self.branch(self.status.contains(CpuFlag::OVERFLOW)),
-                Operation::CLC => self.status.remove(CpuFlag::CARRY),
-                Operation::CLD => self.status.remove(CpuFlag::DECIMAL),
-                Operation::CLI => self.status.remove(CpuFlag::INTERRUPT),
-                Operation::CLV => self.status.remove(CpuFlag::OVERFLOW),
-                Operation::CMP => self.compare(&opcode.mode, self.register_a),
-                Operation::CPX => self.compare(&opcode.mode, self.register_x),
-                Operation::CPY => self.compare(&opcode.mode, self.register_y),
-                Operation::DEC => { self.dec(&opcode.mode); }
-                Operation::DEX => self.dex(),
-                Operation::DEY => self.dey(),
-                Operation::EOR => self.eor(&opcode.mode),
-                Operation::INC => { self.inc(&opcode.mode); }
-                Operation::INX => self.inx(),
-                Operation::INY => self.iny(),
-                Operation::JMP => self.jmp(&opcode.mode),
-                Operation::JSR => self.jsr(),
-                Operation::LDA => self.lda(&opcode.mode),
-                Operation::LDX => self.ldx(&opcode.mode),
-                Operation::LDY => self.ldy(&opcode.mode),
-                Operation::LSR => { self.lsr(&opcode.mode); },
-                Operation::NOP => {},
-                Operation::ORA => self.ora(&opcode.mode),
-                Operation::PHA => todo!(),
-                Operation::PHP => todo!(),
-                Operation::PLA => todo!(),
-                Operation::PLP => todo!(),
-                Operation::ROL => todo!(),
-                Operation::ROR => todo!(),
-                Operation::RTI => todo!(),
-                Operation::RTS => todo!(),
-                Operation::SBC => todo!(),
-                Operation::SEC => todo!(),
-                Operation::SED => todo!(),
-                Operation::SEI => todo!(),
-                Operation::STA => todo!(),
-                Operation::STX => todo!(),
-                Operation::STY => todo!(),
-                Operation::TAX => self.tax(),
-                Operation::TAY => todo!(),
-                Operation::TSX => todo!(),
-                Operation::TXA => todo!(),
-                Operation::TXS => todo!(),
-                Operation::TYA => todo!(),
-            }
-
-            if program_counter_state == self.program_counter {
-                self.program_counter += (opcode.length - 1) as u16;
+        match mode {
+            AddressingMode::Accumulator => {
+                data = self.register_a;
+                addr = 0;
+            }
+            _ => {
+                addr = self.shift_operand_address(mode)?;
+                data = self.mem_read(addr);
             }
         }
-    }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        data = self.rotate_right(data);
 
-    #[test]
-    fn test_0xa9_lda_immediate_load_data() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x05, 0x00]);
+        match mode {
+            AddressingMode::Accumulator => {
+                self.register_a = data;
+                self.update_zero_and_negative_flags(data);
+                Ok(data)
+            }
+            _ => {
+                self.mem_write(addr, data);
+                self.update_zero_and_negative_flags(data);
+                Ok(data)
+            }
+        }
+    }
 
-        assert_eq!(cpu.register_a, 5);
-        assert_eq!(cpu.status.bits() & 0b0000_0010, 0b00);
-        assert_eq!(cpu.status.bits() & 0b1000_0000, 0);
+    fn rti(&mut self) {
+        self.plp();
+        self.program_counter = self.stack_pop_u16();
     }
 
-    #[test]
-    fn test_0xa9_lda_zero_flag() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
+    fn rts(&mut self) {
+        self.program_counter = self.stack_pop_u16().wrapping_add(1);
+    }
 
-        assert_eq!(cpu.status.bits() & 0b0000_0010, 0b10);
+    fn sbc(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
+        if self.cmos_decimal_penalty() {
+            self.cycles += 1;
+        }
+        let value = self.mem_read(addr);
+        self.subtract_with_borrow(value);
+        Ok(())
     }
 
-    #[test]
-    fn test_0xa9_lda_negative_flag() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0xff, 0x00]);
+    fn sta(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        self.mem_write(addr, self.register_a);
+        Ok(())
+    }
 
-        assert_eq!(cpu.status.bits() & 0b1000_0000, 0b1000_0000);
+    fn stx(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        self.mem_write(addr, self.register_x);
+        Ok(())
     }
 
-    #[test]
-    fn test_0xaa_tax_move_a_to_x() {
-        let mut cpu = CPU::new();
-        cpu.register_a = 10;
-        cpu.load_and_run(vec![0xa9, 0x0A,0xaa, 0x00]);
+    fn sty(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        self.mem_write(addr, self.register_y);
+        Ok(())
+    }
 
-        assert_eq!(cpu.register_x, 10)
+    fn tay(&mut self) {
+        self.register_y = self.register_a;
+        self.update_zero_and_negative_flags(self.register_y);
     }
 
-    #[test]
-    fn test_5_ops_working_together() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+    fn tsx(&mut self) {
+        self.register_x = self.stack_pointer;
+        self.update_zero_and_negative_flags(self.register_x);
+    }
 
-        assert_eq!(cpu.register_x, 0xc1)
+    fn txa(&mut self) {
+        self.register_a = self.register_x;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    // TXS is the only transfer that leaves the flags untouched
+    fn txs(&mut self) {
+        self.stack_pointer = self.register_x;
+    }
+
+    fn tya(&mut self) {
+        self.register_a = self.register_y;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    // 65C02 additions
+
+    fn phx(&mut self) {
+        self.stack_push(self.register_x);
+    }
+
+    fn phy(&mut self) {
+        self.stack_push(self.register_y);
+    }
+
+    fn plx(&mut self) {
+        self.register_x = self.stack_pop();
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn ply(&mut self) {
+        self.register_y = self.stack_pop();
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn stz(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        self.mem_write(addr, 0);
+        Ok(())
+    }
+
+    fn trb(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        let data = self.mem_read(addr);
+        self.status.set(CpuFlag::ZERO, self.register_a & data == 0);
+        self.mem_write(addr, data & !self.register_a);
+        Ok(())
+    }
+
+    fn tsb(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        let data = self.mem_read(addr);
+        self.status.set(CpuFlag::ZERO, self.register_a & data == 0);
+        self.mem_write(addr, data | self.register_a);
+        Ok(())
+    }
+
+    fn rmb(&mut self, mode: &AddressingMode, code: u8) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        let data = self.mem_read(addr);
+        self.mem_write(addr, data & !bit_mask(code));
+        Ok(())
+    }
+
+    fn smb(&mut self, mode: &AddressingMode, code: u8) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        let data = self.mem_read(addr);
+        self.mem_write(addr, data | bit_mask(code));
+        Ok(())
+    }
+
+    /// BBR and BBS: tests a bit of a zero page byte, then branches like the
+    /// other branches with the offset in the second operand byte
+    fn branch_on_bit(&mut self, mode: &AddressingMode, code: u8, set: bool) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        let data = self.mem_read(addr);
+        self.branch(self.program_counter.wrapping_add(1), (data & bit_mask(code) != 0) == set);
+        Ok(())
+    }
+
+    /// WAI sleeps until an interrupt line is asserted, modelled as running
+    /// WAI again until then. A masked IRQ still wakes it, and execution
+    /// carries on without taking the interrupt.
+    fn wai(&mut self) {
+        if !self.nmi_pending && !self.irq_asserted() {
+            self.program_counter = self.program_counter.wrapping_sub(1);
+        }
+    }
+
+    // Unofficial opcodes, see https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+
+    fn alr(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        self.and(mode)?;
+        self.lsr(&AddressingMode::Accumulator)?;
+        Ok(())
+    }
+
+    fn anc(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        self.and(mode)?;
+        self.status.set(CpuFlag::CARRY, self.status.contains(CpuFlag::NEGATIVE));
+        Ok(())
+    }
+
+    fn arr(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        self.and(mode)?;
+        self.arr_rotate();
+        Ok(())
+    }
+
+    fn arr_rotate(&mut self) {
+        self.ror(&AddressingMode::Accumulator).ok();
+
+        // C comes from bit 6 and V from bit 6 xor bit 5 of the result
+        let bit_6 = (self.register_a >> 6) & 1;
+        let bit_5 = (self.register_a >> 5) & 1;
+        self.status.set(CpuFlag::CARRY, bit_6 == 1);
+        self.status.set(CpuFlag::OVERFLOW, bit_6 ^ bit_5 == 1);
+    }
+
+    fn axs(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        let data = self.mem_read(addr);
+        self.axs_value(data);
+        Ok(())
+    }
+
+    fn axs_value(&mut self, data: u8) {
+        let x_and_a = self.register_x & self.register_a;
+
+        // Compare-style subtraction: no borrow in, carry set if no borrow out
+        self.status.set(CpuFlag::CARRY, data <= x_and_a);
+        self.register_x = x_and_a.wrapping_sub(data);
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn dcp(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let data = self.dec(mode)?;
+        self.status.set(CpuFlag::CARRY, data <= self.register_a);
+        self.update_zero_and_negative_flags(self.register_a.wrapping_sub(data));
+        Ok(())
+    }
+
+    fn isb(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let data = self.inc(mode)?;
+        self.subtract_with_borrow(data);
+        Ok(())
+    }
+
+    fn las(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
+        let data = self.mem_read(addr);
+        self.las_value(data);
+        Ok(())
+    }
+
+    fn las_value(&mut self, data: u8) {
+        let data = data & self.stack_pointer;
+        self.register_a = data;
+        self.register_x = data;
+        self.stack_pointer = data;
+        self.update_zero_and_negative_flags(data);
+    }
+
+    fn lax(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        self.lda(mode)?;
+        self.register_x = self.register_a;
+        Ok(())
+    }
+
+    // Unstable: the chip ORs A with a magic constant before the AND. $FF is
+    // what most NES consoles show, which makes this a plain load into A and X.
+    fn lxa(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        self.lda(mode)?;
+        self.register_x = self.register_a;
+        Ok(())
+    }
+
+    // The multi-byte NOPs still perform their read
+    fn nop(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        if *mode == AddressingMode::Implied {
+            return Ok(());
+        }
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        if page_cross {
+            self.cycles += 1;
+        }
+        self.mem_read(addr);
+        Ok(())
+    }
+
+    fn rla(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let data = self.rol(mode)?;
+        self.register_a &= data;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    fn rra(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let data = self.ror(mode)?;
+        self.add_with_carry(data);
+        Ok(())
+    }
+
+    fn sax(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        self.mem_write(addr, self.register_a & self.register_x);
+        Ok(())
+    }
+
+    // Shared by SHA/SHX/SHY/TAS
+    fn store_high_and(&mut self, mode: &AddressingMode, index: u8, value: u8) -> Result<(), CpuError> {
+        let (addr, page_cross) = self.get_operand_address(mode)?;
+        let (addr, data) = high_and_store(addr, index, value, page_cross);
+        self.mem_write(addr, data);
+        Ok(())
+    }
+
+    fn slo(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let data = self.asl(mode)?;
+        self.register_a |= data;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    fn sre(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let data = self.lsr(mode)?;
+        self.register_a ^= data;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    fn tas(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        self.stack_pointer = self.register_a & self.register_x;
+        self.store_high_and(mode, self.register_y, self.stack_pointer)
+    }
+
+    // Unstable: A = (A | magic) & X & M, with $EE as the usual magic value
+    fn xaa(&mut self, mode: &AddressingMode) -> Result<(), CpuError> {
+        let (addr, _) = self.get_operand_address(mode)?;
+        let data = self.mem_read(addr);
+        self.xaa_value(data);
+        Ok(())
+    }
+
+    fn xaa_value(&mut self, data: u8) {
+        self.register_a = (self.register_a | 0xEE) & self.register_x & data;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            a: self.register_a,
+            x: self.register_x,
+            y: self.register_y,
+            sp: self.stack_pointer,
+            pc: self.program_counter,
+            p: self.status_with_break(false),
+            cycles: self.cycles,
+        }
+    }
+
+    /// Loads registers and the cycle count from `state`, abandoning any
+    /// instruction `tick` had started. Like PLP, the B flag of `state.p` is
+    /// ignored and the unused bit reads back as set.
+    pub fn set_state(&mut self, state: CpuState) {
+        self.register_a = state.a;
+        self.register_x = state.x;
+        self.register_y = state.y;
+        self.stack_pointer = state.sp;
+        self.program_counter = state.pc;
+        self.status = CpuFlag::from_bits_truncate(state.p);
+        self.status.remove(CpuFlag::BREAK);
+        self.status.insert(CpuFlag::NULL);
+        self.cycles = state.cycles;
+        self.micro = cycle::MicroState::default();
+        self.interrupt_polled = None;
+        self.masked_at_poll = None;
+        self.call_stack.clear();
+    }
+
+    /// Registers to their power-on values, leaving memory as it is
+    pub fn reset(&mut self) {
+        self.register_a = 0;
+        self.register_x = 0;
+        self.register_y = 0;
+        self.stack_pointer = STACK_RESET;
+        self.status = CpuFlag::from_bits_truncate(0b00100100);
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
+        // The reset sequence itself takes 7 cycles
+        self.cycles = 7;
+        self.bus.tick(7);
+        self.micro = cycle::MicroState::default();
+        self.interrupt_polled = None;
+        self.masked_at_poll = None;
+        self.call_stack.clear();
+    }
+
+    /// Turns the console on: RAM gets `ram`, the registers their power-on
+    /// values, and any latched NMI is lost. Cartridge space keeps its
+    /// contents.
+    pub fn power_on(&mut self, ram: RamInit) {
+        if let Some(contents) = self.bus.ram_mut() {
+            ram.fill(contents);
+        }
+        self.nmi_pending = false;
+        self.reset();
+    }
+
+    /// The reset button: A, X, Y and the flags other than I keep their
+    /// values, and the three stack writes of the reset sequence only move
+    /// SP down
+    pub fn soft_reset(&mut self) {
+        self.stack_pointer = self.stack_pointer.wrapping_sub(3);
+        self.disable_interrupts();
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
+        self.cycles += 7;
+        self.bus.tick(7);
+        self.micro = cycle::MicroState::default();
+        self.interrupt_polled = None;
+        self.masked_at_poll = None;
+        self.call_stack.clear();
+    }
+
+    /// Loads `program` at $8000 and starts there on the next reset. Panics if
+    /// the program runs past $FFFF; use `load_at` to get an error instead.
+    pub fn load(&mut self, program: Vec<u8>) {
+        self.load_at(0x8000, &program)
+            .and_then(|_| self.set_entry_point(0x8000))
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Copies `program` into memory starting at `addr`, ROM included. Fails
+    /// on the first byte that nothing at its address holds on to.
+    pub fn load_at(&mut self, addr: u16, program: &[u8]) -> Result<(), CpuError> {
+        if addr as usize + program.len() > 0x10000 {
+            return Err(CpuError::ProgramOutOfBounds { addr, len: program.len() });
+        }
+        for (offset, byte) in program.iter().enumerate() {
+            let addr = addr.wrapping_add(offset as u16);
+            if !self.mem_load(addr, *byte) {
+                return Err(CpuError::ProgramNotWritable { addr });
+            }
+        }
+        Ok(())
+    }
+
+    /// Points the reset vector at `addr`, so the next reset starts there
+    pub fn set_entry_point(&mut self, addr: u16) -> Result<(), CpuError> {
+        self.load_at(RESET_VECTOR, &addr.to_le_bytes())
+    }
+
+    pub fn entry_point(&self) -> u16 {
+        self.peek_u16(RESET_VECTOR)
+    }
+
+    /// Loads and runs `program` until it reaches a BRK
+    pub fn load_and_run(&mut self, program: Vec<u8>) -> Result<StopReason, CpuError> {
+        self.load(program);
+        self.reset();
+        self.stop_on_brk = true;
+        self.run()
+    }
+
+    /// The shadow call stack of subroutines and interrupt handlers entered
+    /// but not yet returned from
+    pub fn backtrace(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    /// Latches a non-maskable interrupt. `step` services it before the next
+    /// instruction; between `tick` calls it has to arrive before the second
+    /// to last cycle of the current instruction, or it waits one more.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Drives the level-sensitive IRQ line for `source`. The interrupt is
+    /// taken before every instruction for as long as any source holds the
+    /// line and the interrupt disable flag is clear.
+    pub fn set_irq_source(&mut self, source: IrqSource, asserted: bool) {
+        self.irq_sources.set(source, asserted);
+    }
+
+    /// Shorthand for driving the `EXTERNAL` IRQ source
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.set_irq_source(IrqSource::EXTERNAL, asserted);
+    }
+
+    pub fn irq_asserted(&self) -> bool {
+        !self.irq_sources.is_empty()
+    }
+
+    /// The sources currently holding IRQ
+    pub fn irq_sources(&self) -> IrqSource {
+        self.irq_sources
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        self.nmi_pending || (self.irq_asserted() && !self.status.contains(CpuFlag::INTERRUPT))
+    }
+
+    /// Decides at an instruction boundary whether the interrupt sequence runs
+    /// next, and for which interrupt
+    fn pending_interrupt(&mut self) -> Option<Interrupt> {
+        let masked = self.masked_at_poll.take()
+            .unwrap_or(self.status.contains(CpuFlag::INTERRUPT));
+        let polled = match self.interrupt_polled.take() {
+            Some(polled) => polled,
+            // Lines changed between two `step` calls count as changed in time
+            // for the poll
+            None => self.nmi_pending || (self.irq_asserted() && !masked),
+        };
+        if !polled {
+            return None;
+        }
+        // Once polled, the sequence runs even if IRQ has been released since
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            Some(Interrupt::Nmi)
+        } else {
+            Some(Interrupt::Irq)
+        }
+    }
+
+    fn interrupt(&mut self, interrupt: Interrupt) {
+        self.stack_push_u16(self.program_counter);
+        self.stack_push(self.status_with_break(interrupt == Interrupt::Brk));
+        self.disable_interrupts();
+        self.program_counter = self.mem_read_u16(interrupt.vector());
+    }
+
+    fn disable_interrupts(&mut self) {
+        self.status.insert(CpuFlag::INTERRUPT);
+        if self.variant.is_cmos() {
+            self.status.remove(CpuFlag::DECIMAL);
+        }
+    }
+    fn update_zero_and_negative_flags(&mut self, result: u8) {
+        if result == 0 {
+            self.status.insert(CpuFlag::ZERO);
+        } else {
+            self.status.remove(CpuFlag::ZERO);
+        }
+
+        if result & 0b1000_0000 != 0 {
+            self.status.insert(CpuFlag::NEGATIVE);
+        } else {
+            self.status.remove(CpuFlag::NEGATIVE);
+        }
+    }
+
+    /// Runs until a BRK (with `stop_on_brk`), an error, or an idle loop that
+    /// no pending interrupt can break out of. Returns `StopReason::Brk` or
+    /// `StopReason::Idle` to tell the two apart.
+    pub fn run(&mut self) -> Result<StopReason, CpuError> {
+        self.run_with_callback(|_| ControlFlow::Continue(()))
+    }
+
+    /// Like `run`, calling `callback` before each instruction. The host can
+    /// use it to feed input or random bytes into memory, refresh a screen,
+    /// or return `ControlFlow::Break` to stop with `StopReason::Breakpoint`.
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> Result<StopReason, CpuError>
+    where
+        F: FnMut(&mut CPU<B>) -> ControlFlow<()>,
+    {
+        loop {
+            let reason = self.run_until_stop(|cpu| {
+                let stop = callback(cpu).is_break();
+                stop.then_some(StopReason::Breakpoint { pc: cpu.program_counter })
+            });
+            match reason {
+                StopReason::Idle { .. } if self.interrupt_pending() => continue,
+                StopReason::Jam { pc, opcode } => return Err(CpuError::Jammed { pc, opcode }),
+                StopReason::Error(error) => return Err(error),
+                reason => return Ok(reason),
+            }
+        }
+    }
+
+    /// Runs whole instructions until `stop` returns a reason, checked before
+    /// each instruction
+    fn run_until_stop<F>(&mut self, mut stop: F) -> StopReason
+    where
+        F: FnMut(&mut CPU<B>) -> Option<StopReason>,
+    {
+        loop {
+            if let Some(reason) = stop(self) {
+                return reason;
+            }
+            if self.stop_on_brk && self.peek(self.program_counter) == 0x00 {
+                return StopReason::Brk { pc: self.program_counter };
+            }
+            match self.step() {
+                Ok(result) if result.is_idle_loop() => return StopReason::Idle { pc: result.pc_before },
+                Ok(_) => {}
+                Err(error) => return error.into(),
+            }
+        }
+    }
+
+    /// Runs exactly `cycles` cycles, one `tick` at a time. The run may end
+    /// part way through an instruction; the next call or `step` picks it up
+    /// from there.
+    pub fn run_for_cycles(&mut self, cycles: u64) -> StopReason {
+        let end = self.cycles + cycles;
+        while self.cycles < end {
+            if !self.mid_instruction() && self.stop_on_brk && self.peek(self.program_counter) == 0x00 {
+                return StopReason::Brk { pc: self.program_counter };
+            }
+            match self.tick() {
+                Ok(Some(result)) if result.is_idle_loop() => return StopReason::Idle { pc: result.pc_before },
+                Ok(_) => {}
+                Err(error) => return error.into(),
+            }
+        }
+        StopReason::BudgetExhausted
+    }
+
+    pub fn run_instructions(&mut self, count: u64) -> StopReason {
+        let mut remaining = count;
+        self.run_until_stop(|_| {
+            if remaining == 0 {
+                return Some(StopReason::BudgetExhausted);
+            }
+            remaining -= 1;
+            None
+        })
+    }
+
+    /// Runs until the program counter reaches `addr`
+    pub fn run_until_pc(&mut self, addr: u16) -> StopReason {
+        self.run_until(|cpu| cpu.program_counter == addr)
+    }
+
+    /// Runs until `predicate` holds before an instruction
+    pub fn run_until<F>(&mut self, mut predicate: F) -> StopReason
+    where
+        F: FnMut(&CPU<B>) -> bool,
+    {
+        self.run_until_stop(|cpu| {
+            predicate(cpu).then_some(StopReason::Breakpoint { pc: cpu.program_counter })
+        })
+    }
+
+    /// Executes exactly one instruction at the current program counter, or
+    /// services a pending NMI/IRQ if there is one
+    pub fn step(&mut self) -> Result<StepResult, CpuError> {
+        // Finish an instruction that `tick` has already started
+        while self.mid_instruction() {
+            if let Some(result) = self.tick()? {
+                return Ok(result);
+            }
+        }
+
+        let result = self.execute_step()?;
+        self.bus.tick(result.cycles);
+        self.call_stack.track(&result, self.stack_pointer);
+        Ok(result)
+    }
+
+    fn execute_step(&mut self) -> Result<StepResult, CpuError> {
+        let pc_before = self.program_counter;
+        let cycles_before = self.cycles;
+
+        if let Some(interrupt) = self.pending_interrupt() {
+            self.interrupt(interrupt);
+            self.cycles += 7;
+            return Ok(StepResult {
+                opcode: 0x00,
+                instruction: self.brk_instruction(),
+                operand_address: None,
+                cycles: 7,
+                pc_before,
+                pc_after: self.program_counter,
+                interrupt: Some(interrupt),
+                warning: None,
+            });
+        }
+        let code = self.mem_read(pc_before);
+        let opcode = self.decode(code, pc_before)?;
+        self.program_counter = self.program_counter.wrapping_add(1);
+        let program_counter_state = self.program_counter;
+        let masked_before = self.status.contains(CpuFlag::INTERRUPT);
+        self.cycles += opcode.cycles as u64;
+
+        let operand_address = match opcode.mode {
+            AddressingMode::Implied
+            | AddressingMode::Accumulator
+            | AddressingMode::NoneAddressing => None,
+            // Peeked, so the operand bytes go over the bus only once
+            AddressingMode::Relative => Some(self.peek_branch_target(self.program_counter)),
+            AddressingMode::ZeroPageRelative => Some(self.peek_branch_target(self.program_counter.wrapping_add(1))),
+            _ => Some(self.peek_operand_address(&opcode.mode)?.0),
+        };
+
+        self.execute(opcode)?;
+
+        // CLI, SEI and PLP change the flag after the poll, so the old value
+        // still decides whether an IRQ follows them
+        if matches!(opcode.operation, Operation::CLI | Operation::SEI | Operation::PLP) {
+            self.masked_at_poll = Some(masked_before);
+        }
+
+        if program_counter_state == self.program_counter {
+            self.program_counter = self.program_counter
+                .wrapping_add((opcode.length - 1) as u16);
+        }
+
+        Ok(StepResult {
+            opcode: code,
+            instruction: opcode,
+            operand_address,
+            cycles: (self.cycles - cycles_before) as u8,
+            pc_before,
+            pc_after: self.program_counter,
+            interrupt: None,
+            warning: self.decode_warning(opcode, pc_before),
+        })
+    }
+
+    /// Looks up `code` for the current variant and applies the unofficial
+    /// opcode policy
+    fn decode(&self, code: u8, pc: u16) -> Result<&'static Instruction, CpuError> {
+        self.decode_from(decode_table(self.variant), code, pc)
+    }
+
+    /// `decode` against `table` rather than the variant's own
+    fn decode_from(&self, table: &DecodeTable, code: u8, pc: u16) -> Result<&'static Instruction, CpuError> {
+        let opcode = table[code as usize]
+            .ok_or(CpuError::UnknownOpcode { pc, opcode: code })?;
+
+        if !opcode.official {
+            match self.unofficial_opcodes {
+                // `Warn` reaches the caller through `StepResult::warning`
+                UnofficialOpcodePolicy::Execute | UnofficialOpcodePolicy::Warn => {}
+                UnofficialOpcodePolicy::Reject => {
+                    return Err(CpuError::UnofficialOpcode { pc, opcode: code });
+                }
+            }
+        }
+
+        // STP stops the clock until a reset, just like a JAM
+        if matches!(opcode.operation, Operation::JAM | Operation::STP) {
+            return Err(CpuError::Jammed { pc, opcode: code });
+        }
+        Ok(opcode)
+    }
+
+    /// What `StepResult::warning` reports for `instruction` fetched at `pc`
+    fn decode_warning(&self, instruction: &Instruction, pc: u16) -> Option<CpuError> {
+        let warn = !instruction.official && self.unofficial_opcodes == UnofficialOpcodePolicy::Warn;
+        warn.then_some(CpuError::UnofficialOpcode { pc, opcode: instruction.code })
+    }
+
+    /// The BRK the CPU forces into its instruction register for NMI/IRQ
+    fn brk_instruction(&self) -> &'static Instruction {
+        decode_table(self.variant)[0x00].expect("BRK is defined on every variant")
+    }
+
+    fn branch_condition(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::BCC => !self.status.contains(CpuFlag::CARRY),
+            Operation::BCS => self.status.contains(CpuFlag::CARRY),
+            Operation::BEQ => self.status.contains(CpuFlag::ZERO),
+            Operation::BMI => self.status.contains(CpuFlag::NEGATIVE),
+            Operation::BNE => !self.status.contains(CpuFlag::ZERO),
+            Operation::BPL => !self.status.contains(CpuFlag::NEGATIVE),
+            Operation::BVC => !self.status.contains(CpuFlag::OVERFLOW),
+            Operation::BVS => self.status.contains(CpuFlag::OVERFLOW),
+            Operation::BRA => true,
+            _ => false,
+        }
+    }
+
+    /// Runs the operation of `opcode` with the program counter on its first
+    /// operand byte
+    fn execute(&mut self, opcode: &Instruction) -> Result<(), CpuError> {
+        match opcode.operation {
+            Operation::ADC => self.adc(&opcode.mode)?,
+            Operation::AND => self.and(&opcode.mode)?,
+            Operation::ASL => { self.asl(&opcode.mode)?; }
+            Operation::BCC | Operation::BCS | Operation::BEQ | Operation::BMI | Operation::BNE
+            | Operation::BPL | Operation::BVC | Operation::BVS | Operation::BRA => {
+                self.branch(self.program_counter, self.branch_condition(opcode.operation));
+            }
+            Operation::BIT => self.bit(&opcode.mode)?,
+            Operation::BRK => self.brk(),
+            Operation::CLC => self.status.remove(CpuFlag::CARRY),
+            Operation::CLD => self.status.remove(CpuFlag::DECIMAL),
+            Operation::CLI => self.status.remove(CpuFlag::INTERRUPT),
+            Operation::CLV => self.status.remove(CpuFlag::OVERFLOW),
+            Operation::CMP => self.compare(&opcode.mode, self.register_a)?,
+            Operation::CPX => self.compare(&opcode.mode, self.register_x)?,
+            Operation::CPY => self.compare(&opcode.mode, self.register_y)?,
+            Operation::DEC => { self.dec(&opcode.mode)?; }
+            Operation::DEX => self.dex(),
+            Operation::DEY => self.dey(),
+            Operation::EOR => self.eor(&opcode.mode)?,
+            Operation::INC => { self.inc(&opcode.mode)?; }
+            Operation::INX => self.inx(),
+            Operation::INY => self.iny(),
+            Operation::JMP => self.jmp(&opcode.mode)?,
+            Operation::JSR => self.jsr(),
+            Operation::LDA => self.lda(&opcode.mode)?,
+            Operation::LDX => self.ldx(&opcode.mode)?,
+            Operation::LDY => self.ldy(&opcode.mode)?,
+            Operation::LSR => { self.lsr(&opcode.mode)?; }
+            Operation::NOP => self.nop(&opcode.mode)?,
+            Operation::ORA => self.ora(&opcode.mode)?,
+            Operation::PHA => self.pha(),
+            Operation::PHP => self.php(),
+            Operation::PLA => self.pla(),
+            Operation::PLP => self.plp(),
+            Operation::ROL => { self.rol(&opcode.mode)?; }
+            Operation::ROR => { self.ror(&opcode.mode)?; }
+            Operation::RTI => self.rti(),
+            Operation::RTS => self.rts(),
+            Operation::SBC => self.sbc(&opcode.mode)?,
+            Operation::SEC => self.status.insert(CpuFlag::CARRY),
+            Operation::SED => self.status.insert(CpuFlag::DECIMAL),
+            Operation::SEI => self.status.insert(CpuFlag::INTERRUPT),
+            Operation::STA => self.sta(&opcode.mode)?,
+            Operation::STX => self.stx(&opcode.mode)?,
+            Operation::STY => self.sty(&opcode.mode)?,
+            Operation::TAX => self.tax(),
+            Operation::TAY => self.tay(),
+            Operation::TSX => self.tsx(),
+            Operation::TXA => self.txa(),
+            Operation::TXS => self.txs(),
+            Operation::TYA => self.tya(),
+            Operation::ALR => self.alr(&opcode.mode)?,
+            Operation::ANC => self.anc(&opcode.mode)?,
+            Operation::ARR => self.arr(&opcode.mode)?,
+            Operation::AXS => self.axs(&opcode.mode)?,
+            Operation::DCP => self.dcp(&opcode.mode)?,
+            Operation::ISB => self.isb(&opcode.mode)?,
+            Operation::JAM => unreachable!("JAM is handled before dispatch"),
+            Operation::LAS => self.las(&opcode.mode)?,
+            Operation::LAX => self.lax(&opcode.mode)?,
+            Operation::LXA => self.lxa(&opcode.mode)?,
+            Operation::RLA => self.rla(&opcode.mode)?,
+            Operation::RRA => self.rra(&opcode.mode)?,
+            Operation::SAX => self.sax(&opcode.mode)?,
+            Operation::SHA => self.store_high_and(&opcode.mode, self.register_y, self.register_a & self.register_x)?,
+            Operation::SHX => self.store_high_and(&opcode.mode, self.register_y, self.register_x)?,
+            Operation::SHY => self.store_high_and(&opcode.mode, self.register_x, self.register_y)?,
+            Operation::SLO => self.slo(&opcode.mode)?,
+            Operation::SRE => self.sre(&opcode.mode)?,
+            Operation::TAS => self.tas(&opcode.mode)?,
+            Operation::XAA => self.xaa(&opcode.mode)?,
+            Operation::PHX => self.phx(),
+            Operation::PHY => self.phy(),
+            Operation::PLX => self.plx(),
+            Operation::PLY => self.ply(),
+            Operation::STZ => self.stz(&opcode.mode)?,
+            Operation::TRB => self.trb(&opcode.mode)?,
+            Operation::TSB => self.tsb(&opcode.mode)?,
+            Operation::RMB => self.rmb(&opcode.mode, opcode.code)?,
+            Operation::SMB => self.smb(&opcode.mode, opcode.code)?,
+            Operation::BBR => self.branch_on_bit(&opcode.mode, opcode.code, false)?,
+            Operation::BBS => self.branch_on_bit(&opcode.mode, opcode.code, true)?,
+            Operation::WAI => self.wai(),
+            Operation::STP => unreachable!("STP is handled before dispatch"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu_types::IRQ_VECTOR;
+
+    #[test]
+    fn test_0xa9_lda_immediate_load_data() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x05, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 5);
+        assert_eq!(cpu.status.bits() & 0b0000_0010, 0b00);
+        assert_eq!(cpu.status.bits() & 0b1000_0000, 0);
+    }
+
+    #[test]
+    fn test_0xa9_lda_zero_flag() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x00, 0x00]).unwrap();
+
+        assert_eq!(cpu.status.bits() & 0b0000_0010, 0b10);
+    }
+
+    #[test]
+    fn test_0xa9_lda_negative_flag() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0xff, 0x00]).unwrap();
+
+        assert_eq!(cpu.status.bits() & 0b1000_0000, 0b1000_0000);
+    }
+
+    #[test]
+    fn test_0xaa_tax_move_a_to_x() {
+        let mut cpu = CPU::new();
+        cpu.register_a = 10;
+        cpu.load_and_run(vec![0xa9, 0x0A,0xaa, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_x, 10)
+    }
+
+    #[test]
+    fn test_5_ops_working_together() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_x, 0xc1)
     }
 
     #[test]
     fn test_inx_overflow() {
         let mut cpu = CPU::new();
         cpu.register_x = 0xff;
-        cpu.load_and_run(vec![0xa9, 0xff, 0xaa,0xe8, 0xe8, 0x00]);
+        cpu.load_and_run(vec![0xa9, 0xff, 0xaa,0xe8, 0xe8, 0x00]).unwrap();
 
         assert_eq!(cpu.register_x, 1)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_0x85_sta_zero_page() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x55, 0x85, 0x10, 0x00]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x10), 0x55);
+    }
+
+    #[test]
+    fn test_0xe9_sbc_borrow() {
+        let mut cpu = CPU::new();
+        // SEC; LDA #$05; SBC #$06
+        cpu.load_and_run(vec![0x38, 0xa9, 0x05, 0xe9, 0x06, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0xff);
+        assert!(!cpu.status.contains(CpuFlag::CARRY));
+        assert!(cpu.status.contains(CpuFlag::NEGATIVE));
+    }
+
+    #[test]
+    fn test_pha_pla_round_trip() {
+        let mut cpu = CPU::new();
+        // LDA #$42; PHA; LDA #$00; PLA
+        cpu.load_and_run(vec![0xa9, 0x42, 0x48, 0xa9, 0x00, 0x68, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+    }
+
+    #[test]
+    fn test_php_pushes_break_flag() {
+        let mut cpu = CPU::new();
+        // SEC; PHP; PLA
+        cpu.load_and_run(vec![0x38, 0x08, 0x68, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0b0011_0101);
+    }
+
+    #[test]
+    fn test_0x2a_rol_and_0x6a_ror_accumulator() {
+        let mut cpu = CPU::new();
+        // SEC; LDA #$80; ROL A
+        cpu.load_and_run(vec![0x38, 0xa9, 0x80, 0x2a, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+
+        // SEC; LDA #$01; ROR A
+        cpu.load_and_run(vec![0x38, 0xa9, 0x01, 0x6a, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+        assert!(cpu.status.contains(CpuFlag::NEGATIVE));
+    }
+
+    #[test]
+    fn test_jsr_rts() {
+        let mut cpu = CPU::new();
+        // JSR $8006; LDX #$01; BRK; LDY #$02; RTS
+        cpu.load_and_run(vec![0x20, 0x06, 0x80, 0xa2, 0x01, 0x00, 0xa0, 0x02, 0x60]).unwrap();
+
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.register_y, 2);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+    }
+
+    #[test]
+    fn test_transfers() {
+        let mut cpu = CPU::new();
+        // LDA #$07; TAY; LDX #$ff; TXS; TSX; TXA; TYA
+        cpu.load_and_run(vec![0xa9, 0x07, 0xa8, 0xa2, 0xff, 0x9a, 0xba, 0x8a, 0x98, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_y, 0x07);
+        assert_eq!(cpu.stack_pointer, 0xff);
+        assert_eq!(cpu.register_x, 0xff);
+        assert_eq!(cpu.register_a, 0x07);
+    }
+
+    #[test]
+    fn test_0xd0_bne_loop() {
+        let mut cpu = CPU::new();
+        // LDX #$05; DEX; BNE -3
+        cpu.load_and_run(vec![0xa2, 0x05, 0xca, 0xd0, 0xfd, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_x, 0);
+        assert!(cpu.status.contains(CpuFlag::ZERO));
+    }
+
+    #[test]
+    fn test_0x20_jsr_at_the_top_of_memory() {
+        let mut cpu = CPU::new();
+        // JSR $8000 with its operand in $FFFE-$FFFF
+        cpu.poke(0xfffd, 0x20);
+        cpu.poke_u16(0xfffe, 0x8000);
+        cpu.program_counter = 0xfffd;
+        cpu.stack_pointer = STACK_RESET;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x8000);
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0xffff);
+    }
+
+    #[test]
+    fn test_unknown_opcode_is_an_error() {
+        let cpu = CPU::new();
+        let mut table = *decode_table(CpuVariant::Ricoh2A03);
+        table[0xa9] = None;
+
+        let error = cpu.decode_from(&table, 0xa9, 0x8000).unwrap_err();
+        assert_eq!(error, CpuError::UnknownOpcode { pc: 0x8000, opcode: 0xa9 });
+        assert_eq!(error.to_string(), "unknown opcode $A9 at $8000");
+    }
+
+    #[test]
+    fn test_step_executes_one_instruction() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x05, 0x8d, 0x00, 0x02, 0xd0, 0xfe]);
+        cpu.reset();
+
+        let result = cpu.step().unwrap();
+        assert_eq!(result.opcode, 0xa9);
+        assert_eq!(result.instruction.operation, Operation::LDA);
+        assert_eq!(result.operand_address, Some(0x8001));
+        assert_eq!((result.pc_before, result.pc_after), (0x8000, 0x8002));
+        assert_eq!(result.cycles, 2);
+        assert_eq!(cpu.register_a, 5);
+
+        let result = cpu.step().unwrap();
+        assert_eq!(result.operand_address, Some(0x0200));
+        assert_eq!(cpu.mem_read(0x0200), 5);
+
+        let result = cpu.step().unwrap();
+        assert_eq!(result.operand_address, Some(0x8005));
+        assert_eq!(result.pc_after, 0x8005);
+    }
+
+    #[test]
+    fn test_page_cross_adds_a_cycle() {
+        let mut cpu = CPU::new();
+        // LDX #$01; LDA $80FF,X; LDA $8000,X
+        cpu.load(vec![0xa2, 0x01, 0xbd, 0xff, 0x80, 0xbd, 0x00, 0x80]);
+        cpu.reset();
+
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        assert_eq!(cpu.step().unwrap().cycles, 4);
+        assert_eq!(cpu.cycles, 7 + 11);
+    }
+
+    #[test]
+    fn test_branch_cycles() {
+        let mut cpu = CPU::new();
+        // SEC; BCC +0 (not taken); BCS +0 (taken, same page)
+        cpu.load(vec![0x38, 0x90, 0x00, 0xb0, 0x00]);
+        cpu.reset();
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.step().unwrap().cycles, 3);
+
+        // BCS -128 from $8002 lands on the previous page
+        cpu.load(vec![0x38, 0xb0, 0x80]);
+        cpu.reset();
+        cpu.step().unwrap();
+        let result = cpu.step().unwrap();
+        assert_eq!(result.pc_after, 0x7f83);
+        assert_eq!(result.cycles, 4);
+    }
+
+    #[test]
+    fn test_brk_jumps_through_irq_vector() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0x38, 0x00, 0xea, 0xe8]);
+        cpu.reset();
+        cpu.poke_u16(IRQ_VECTOR, 0x9000);
+        cpu.poke(0x9000, 0x40); // RTI
+
+        cpu.step().unwrap();
+        let result = cpu.step().unwrap();
+        assert_eq!(result.pc_after, 0x9000);
+        assert_eq!(result.cycles, 7);
+        assert!(cpu.status.contains(CpuFlag::INTERRUPT));
+        assert_eq!(cpu.mem_read(0x01fb), 0b0011_0101);
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0x8003);
+
+        // RTI resumes after the padding byte with the old flags
+        assert_eq!(cpu.step().unwrap().pc_after, 0x8003);
+        assert_eq!(cpu.status.bits(), 0b0010_0101);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+    }
+
+    #[test]
+    fn test_nmi_and_irq() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xea, 0xea, 0xea]);
+        cpu.reset();
+        cpu.poke_u16(NMI_VECTOR, 0x9000);
+        cpu.poke_u16(IRQ_VECTOR, 0xa000);
+
+        // I is set after reset, so IRQ is masked
+        cpu.set_irq(true);
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+
+        cpu.trigger_nmi();
+        let result = cpu.step().unwrap();
+        assert_eq!(result.interrupt, Some(Interrupt::Nmi));
+        assert_eq!(result.pc_after, 0x9000);
+        assert_eq!(cpu.mem_read(0x01fb) & CpuFlag::BREAK.bits(), 0);
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0x8001);
+
+        cpu.status.remove(CpuFlag::INTERRUPT);
+        let result = cpu.step().unwrap();
+        assert_eq!(result.interrupt, Some(Interrupt::Irq));
+        assert_eq!(result.pc_after, 0xa000);
+        assert_eq!(cpu.cycles, 7 + 2 + 7 + 7);
+    }
+
+    #[test]
+    fn test_jam_is_an_error() {
+        let mut cpu = CPU::new();
+        let result = cpu.load_and_run(vec![0xa9, 0x01, 0x02, 0x00]);
+
+        assert_eq!(result, Err(CpuError::Jammed { pc: 0x8002, opcode: 0x02 }));
+        assert_eq!(cpu.program_counter, 0x8002);
+    }
+
+    #[test]
+    fn test_unofficial_opcode_policy() {
+        let mut cpu = CPU::new();
+        cpu.unofficial_opcodes = UnofficialOpcodePolicy::Reject;
+        let result = cpu.load_and_run(vec![0xa9, 0x01, 0xea, 0x1a, 0x00]);
+
+        assert_eq!(result, Err(CpuError::UnofficialOpcode { pc: 0x8003, opcode: 0x1a }));
+
+        cpu.unofficial_opcodes = UnofficialOpcodePolicy::Warn;
+        cpu.reset();
+        cpu.run_instructions(2);
+        let result = cpu.step().unwrap();
+        assert_eq!(result.warning, Some(CpuError::UnofficialOpcode { pc: 0x8003, opcode: 0x1a }));
+        assert_eq!(cpu.step().unwrap().warning, None);
+    }
+
+    #[test]
+    fn test_0xa7_lax_and_0x87_sax() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x10, 0x8f);
+        // LAX $10; LDA #$f0; SAX $11
+        cpu.load_and_run(vec![0xa7, 0x10, 0xa9, 0xf0, 0x87, 0x11, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_x, 0x8f);
+        assert_eq!(cpu.mem_read(0x11), 0x80);
+    }
+
+    #[test]
+    fn test_0xc7_dcp_and_0xe7_isb() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x10, 0x06);
+        cpu.mem_write(0x11, 0x01);
+        // LDA #$05; DCP $10; SEC; ISB $11
+        cpu.load_and_run(vec![0xa9, 0x05, 0xc7, 0x10, 0x38, 0xe7, 0x11, 0x00]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x10), 0x05);
+        assert_eq!(cpu.mem_read(0x11), 0x02);
+        assert_eq!(cpu.register_a, 0x03);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+    }
+
+    #[test]
+    fn test_0x07_slo_and_0x47_sre() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x10, 0x81);
+        cpu.mem_write(0x11, 0x03);
+        // LDA #$10; SLO $10
+        cpu.load_and_run(vec![0xa9, 0x10, 0x07, 0x10, 0x00]).unwrap();
+        assert_eq!(cpu.mem_read(0x10), 0x02);
+        assert_eq!(cpu.register_a, 0x12);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+
+        // LDA #$ff; SRE $11
+        cpu.load_and_run(vec![0xa9, 0xff, 0x47, 0x11, 0x00]).unwrap();
+        assert_eq!(cpu.mem_read(0x11), 0x01);
+        assert_eq!(cpu.register_a, 0xfe);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+    }
+
+    #[test]
+    fn test_immediate_combined_opcodes() {
+        let mut cpu = CPU::new();
+        // LDA #$ff; ANC #$80
+        cpu.load_and_run(vec![0xa9, 0xff, 0x0b, 0x80, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+
+        // LDA #$ff; ALR #$03
+        cpu.load_and_run(vec![0xa9, 0xff, 0x4b, 0x03, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+
+        // SEC; LDA #$ff; ARR #$c0
+        cpu.load_and_run(vec![0x38, 0xa9, 0xff, 0x6b, 0xc0, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0xe0);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+        assert!(!cpu.status.contains(CpuFlag::OVERFLOW));
+
+        // LDA #$0f; LDX #$fc; AXS #$02
+        cpu.load_and_run(vec![0xa9, 0x0f, 0xa2, 0xfc, 0xcb, 0x02, 0x00]).unwrap();
+        assert_eq!(cpu.register_x, 0x0a);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+    }
+
+    #[test]
+    fn test_0x1c_nop_absolute_x_page_cross() {
+        let mut cpu = CPU::new();
+        // LDX #$01; NOP $80ff,X
+        cpu.load(vec![0xa2, 0x01, 0x1c, 0xff, 0x80]);
+        cpu.reset();
+        cpu.step().unwrap();
+
+        let result = cpu.step().unwrap();
+        assert_eq!(result.cycles, 5);
+        assert_eq!(result.pc_after, 0x8005);
+    }
+
+    #[test]
+    fn test_2a03_ignores_decimal_flag() {
+        let mut cpu = CPU::new();
+        // SED; CLC; LDA #$09; ADC #$01
+        cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x09, 0x69, 0x01, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x0a);
+    }
+
+    #[test]
+    fn test_nmos_decimal_adc_and_sbc() {
+        let mut cpu = CPU::with_variant(CpuVariant::Nmos6502);
+        // SED; SEC; LDA #$58; ADC #$46
+        cpu.load_and_run(vec![0xf8, 0x38, 0xa9, 0x58, 0x69, 0x46, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x05);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+
+        // SED; CLC; LDA #$99; ADC #$01: Z follows the binary sum $9A
+        cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status.contains(CpuFlag::CARRY));
+        assert!(!cpu.status.contains(CpuFlag::ZERO));
+        assert!(cpu.status.contains(CpuFlag::NEGATIVE));
+
+        // SED; SEC; LDA #$12; SBC #$21
+        cpu.load_and_run(vec![0xf8, 0x38, 0xa9, 0x12, 0xe9, 0x21, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x91);
+        assert!(!cpu.status.contains(CpuFlag::CARRY));
+    }
+
+    #[test]
+    fn test_nmos_decimal_matches_bcd_arithmetic() {
+        let to_bcd = |n: u16| ((((n / 10) % 10) << 4) | (n % 10)) as u8;
+        let mut cpu = CPU::with_variant(CpuVariant::Nmos6502);
+
+        for a in 0..100 {
+            for m in 0..100 {
+                for carry in [false, true] {
+                    cpu.status = CpuFlag::DECIMAL;
+                    cpu.status.set(CpuFlag::CARRY, carry);
+                    cpu.register_a = to_bcd(a);
+                    cpu.add_with_carry(to_bcd(m));
+                    let sum = a + m + carry as u16;
+                    assert_eq!(cpu.register_a, to_bcd(sum % 100));
+                    assert_eq!(cpu.status.contains(CpuFlag::CARRY), sum >= 100);
+
+                    cpu.status = CpuFlag::DECIMAL;
+                    cpu.status.set(CpuFlag::CARRY, carry);
+                    cpu.register_a = to_bcd(a);
+                    cpu.subtract_with_borrow(to_bcd(m));
+                    let difference = 100 + a - m - !carry as u16;
+                    assert_eq!(cpu.register_a, to_bcd(difference % 100));
+                    assert_eq!(cpu.status.contains(CpuFlag::CARRY), difference >= 100);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_wrap() {
+        let program = vec![0x6c, 0xff, 0x02];
+
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x02ff, 0x34);
+        cpu.mem_write(0x0200, 0x12);
+        cpu.mem_write(0x0300, 0x56);
+        cpu.load(program.clone());
+        cpu.reset();
+        assert_eq!(cpu.step().unwrap().pc_after, 0x1234);
+
+        let mut cpu = CPU::with_variant(CpuVariant::Wdc65C02);
+        cpu.mem_write(0x02ff, 0x34);
+        cpu.mem_write(0x0200, 0x12);
+        cpu.mem_write(0x0300, 0x56);
+        cpu.load(program);
+        cpu.reset();
+        let result = cpu.step().unwrap();
+        assert_eq!(result.pc_after, 0x5634);
+        assert_eq!(result.cycles, 6);
+    }
+
+    #[test]
+    fn test_65c02_instructions() {
+        let mut cpu = CPU::with_variant(CpuVariant::Wdc65C02);
+        cpu.mem_write(0x10, 0x00);
+        cpu.mem_write(0x11, 0x02);
+        cpu.mem_write(0x0200, 0x0f);
+        cpu.mem_write(0x20, 0xff);
+        cpu.load_and_run(vec![
+            0xa9, 0x3c,       // LDA #$3c
+            0x14, 0x20,       // TRB $20
+            0x04, 0x21,       // TSB $21
+            0x64, 0x10,       // STZ $10
+            0xb2, 0x10,       // LDA ($10)
+            0x1a,             // INC A
+            0xa2, 0x07,       // LDX #$07
+            0xda,             // PHX
+            0x7a,             // PLY
+            0x80, 0x01,       // BRA +1
+            0xea,             // NOP (skipped)
+            0x89, 0x00,       // BIT #$00
+        ]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x20), 0xc3);
+        assert_eq!(cpu.mem_read(0x21), 0x3c);
+        assert_eq!(cpu.mem_read(0x10), 0x00);
+        assert_eq!(cpu.register_a, 0x10);
+        assert_eq!(cpu.register_y, 0x07);
+        assert!(cpu.status.contains(CpuFlag::ZERO));
+        assert!(!cpu.status.contains(CpuFlag::NEGATIVE));
+    }
+
+    #[test]
+    fn test_65c02_decimal_flags_and_undefined_opcodes() {
+        let mut cpu = CPU::with_variant(CpuVariant::Wdc65C02);
+        // SED; CLC; LDA #$99; ADC #$01
+        cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status.contains(CpuFlag::ZERO));
+        assert!(!cpu.status.contains(CpuFlag::NEGATIVE));
+
+        // $02 is a two byte NOP instead of a JAM, STP stops the CPU like one
+        let result = cpu.load_and_run(vec![0x02, 0xff, 0xdb, 0x00]);
+        assert_eq!(result, Err(CpuError::Jammed { pc: 0x8002, opcode: 0xdb }));
+    }
+
+    #[test]
+    fn test_65c02_bit_instructions() {
+        let mut cpu = CPU::with_variant(CpuVariant::Wdc65C02);
+        cpu.mem_write(0x10, 0xff);
+        cpu.load_and_run(vec![
+            0x37, 0x10,       // RMB3 $10
+            0xc7, 0x11,       // SMB4 $11
+            0x3f, 0x10, 0x02, // BBR3 $10,+2
+            0xa2, 0x01,       // LDX #$01 (skipped)
+            0xcf, 0x11, 0x02, // BBS4 $11,+2
+            0xa0, 0x01,       // LDY #$01 (skipped)
+            0x0f, 0x10, 0x02, // BBR0 $10,+2 (not taken)
+            0xa9, 0x2a,       // LDA #$2a
+        ]).unwrap();
+
+        assert_eq!(cpu.mem_read(0x10), 0xf7);
+        assert_eq!(cpu.mem_read(0x11), 0x10);
+        assert_eq!((cpu.register_a, cpu.register_x, cpu.register_y), (0x2a, 0x00, 0x00));
+    }
+
+    #[test]
+    fn test_65c02_bit_branch_cycles() {
+        let mut cpu = CPU::with_variant(CpuVariant::Wdc65C02);
+        // BBS0 $10,+$10 at $80FC, first with bit 0 clear and then set
+        cpu.poke(0x80fc, 0x8f);
+        cpu.poke(0x80fd, 0x10);
+        cpu.poke(0x80fe, 0x10);
+        cpu.program_counter = 0x80fc;
+        let result = cpu.step().unwrap();
+        assert_eq!((result.cycles, result.pc_after, result.operand_address), (5, 0x80ff, Some(0x810f)));
+
+        // Taken, to the next page
+        cpu.mem_write(0x10, 0x01);
+        cpu.program_counter = 0x80fc;
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.program_counter, 0x810f);
+    }
+
+    #[test]
+    fn test_65c02_wai_waits_for_an_interrupt() {
+        let mut cpu = CPU::with_variant(CpuVariant::Wdc65C02);
+        // WAI; LDA #$01
+        cpu.load(vec![0xcb, 0xa9, 0x01]);
+        cpu.reset();
+        assert_eq!(cpu.step().unwrap().pc_after, 0x8000);
+        assert_eq!(cpu.step().unwrap().pc_after, 0x8000);
+
+        // With I set the IRQ only wakes it up
+        cpu.set_irq(true);
+        assert_eq!(cpu.step().unwrap().pc_after, 0x8001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+    }
+
+    #[test]
+    fn test_run_with_callback() {
+        // loop: LDA $FE; STA $10; JMP loop
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa5, 0xfe, 0x85, 0x10, 0x4c, 0x00, 0x80]);
+        cpu.reset();
+
+        let mut calls = 0;
+        let reason = cpu.run_with_callback(|cpu| {
+            calls += 1;
+            cpu.mem_write(0xfe, calls);
+            if calls == 5 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(reason, Ok(StopReason::Breakpoint { pc: 0x8002 }));
+
+        // Four instructions ran: the second LDA saw the byte from the fourth call
+        assert_eq!(calls, 5);
+        assert_eq!(cpu.program_counter, 0x8002);
+        assert_eq!(cpu.register_a, 4);
+        assert_eq!(cpu.mem_read(0x10), 1);
+    }
+
+    #[test]
+    fn test_run_with_callback_stops_like_run() {
+        // LDA #$01; BRK
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x01, 0x00]);
+        cpu.reset();
+        cpu.stop_on_brk = true;
+        let reason = cpu.run_with_callback(|_| ControlFlow::Continue(()));
+        assert_eq!(reason, Ok(StopReason::Brk { pc: 0x8002 }));
+
+        // JMP *
+        cpu.load(vec![0x4c, 0x00, 0x80]);
+        cpu.reset();
+        let mut calls = 0;
+        let reason = cpu.run_with_callback(|_| {
+            calls += 1;
+            ControlFlow::Continue(())
+        });
+        assert_eq!(reason, Ok(StopReason::Idle { pc: 0x8000 }));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_bounded_runs() {
+        // loop: INX; BNE loop; BRK
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xe8, 0xd0, 0xfd, 0x00]);
+        cpu.reset();
+
+        assert_eq!(cpu.run_instructions(3), StopReason::BudgetExhausted);
+        assert_eq!(cpu.register_x, 2);
+
+        assert_eq!(cpu.run_until(|cpu| cpu.register_x == 0x10), StopReason::Breakpoint { pc: 0x8001 });
+        assert_eq!(cpu.run_until_pc(0x8003), StopReason::Breakpoint { pc: 0x8003 });
+        assert_eq!(cpu.register_x, 0);
+
+        cpu.stop_on_brk = true;
+        assert_eq!(cpu.run_instructions(10), StopReason::Brk { pc: 0x8003 });
+    }
+
+    #[test]
+    fn test_run_for_cycles_is_exact() {
+        // loop: LDA $1234; JMP loop, 7 cycles per iteration
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xad, 0x34, 0x12, 0x4c, 0x00, 0x80]);
+        cpu.reset();
+
+        assert_eq!(cpu.run_for_cycles(100), StopReason::BudgetExhausted);
+        assert_eq!(cpu.cycles, 107);
+        assert_eq!(cpu.run_for_cycles(5), StopReason::BudgetExhausted);
+        assert_eq!(cpu.cycles, 112);
+    }
+
+    #[test]
+    fn test_bounded_run_stops_on_jam() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xea, 0x02]);
+        cpu.reset();
+        assert_eq!(cpu.run_for_cycles(100), StopReason::Jam { pc: 0x8001, opcode: 0x02 });
+
+        cpu.unofficial_opcodes = UnofficialOpcodePolicy::Reject;
+        cpu.reset();
+        assert_eq!(
+            cpu.run_instructions(5),
+            StopReason::Error(CpuError::UnofficialOpcode { pc: 0x8001, opcode: 0x02 })
+        );
+    }
+
+    #[test]
+    fn test_state_snapshot() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x80, 0xaa, 0x00]);
+        cpu.reset();
+        let start = cpu.state();
+        assert_eq!(start.to_string(), "8000  A:00 X:00 Y:00 P:24 SP:FD CYC:7");
+
+        cpu.run_instructions(2);
+        let expected = CpuState { a: 0x80, x: 0x80, p: 0xA4, pc: 0x8003, cycles: 11, ..start };
+        assert_eq!(cpu.state(), expected);
+        assert_eq!(
+            start.diff(&cpu.state()),
+            "A:00 -> A:80, X:00 -> X:80, PC:8000 -> PC:8003, P:24 -> P:A4 (+N), CYC:7 -> CYC:11"
+        );
+        assert_eq!(expected.diff(&cpu.state()), "");
+
+        cpu.set_state(start);
+        assert_eq!(cpu.state(), start);
+    }
+
+    #[test]
+    fn test_state_p_reads_like_php_without_b() {
+        let mut cpu = CPU::new();
+        assert_eq!(cpu.state().p, 0x20);
+
+        cpu.set_state(CpuState { p: 0xdf, ..cpu.state() });
+        assert_eq!(cpu.state().p, 0xef);
+        assert_eq!(cpu.status.bits(), 0xef);
+    }
+
+    #[test]
+    fn test_load_at_and_entry_point() {
+        // JSR $0606; BRK; ...; $0606: LDA #$42; RTS
+        let mut cpu = CPU::new();
+        cpu.load_at(0x0600, &[0x20, 0x06, 0x06, 0x00, 0x00, 0x00, 0xa9, 0x42, 0x60]).unwrap();
+        cpu.set_entry_point(0x0600).unwrap();
+        assert_eq!(cpu.entry_point(), 0x0600);
+        cpu.reset();
+        cpu.stop_on_brk = true;
+        assert_eq!(cpu.run(), Ok(StopReason::Brk { pc: 0x0603 }));
+
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.program_counter, 0x0603);
+    }
+
+    #[test]
+    fn test_load_at_out_of_bounds() {
+        let mut cpu = CPU::new();
+        assert_eq!(
+            cpu.load_at(0xfffe, &[1, 2, 3]),
+            Err(CpuError::ProgramOutOfBounds { addr: 0xfffe, len: 3 })
+        );
+        assert_eq!(cpu.load_at(0xfffd, &[1, 2, 3]), Ok(()));
+    }
+
+    #[test]
+    fn test_load_at_writes_cartridge_rom() {
+        let mut cpu = CPU::new();
+        cpu.load_at(0xc000, &[0xa9, 0x01]).unwrap();
+        cpu.set_entry_point(0xc000).unwrap();
+        assert_eq!((cpu.peek(0xc000), cpu.peek(0xc001)), (0xa9, 0x01));
+        assert_eq!(cpu.entry_point(), 0xc000);
+        // Programs still can't write to ROM
+        cpu.mem_write(0xc000, 0xea);
+        assert_eq!(cpu.peek(0xc000), 0xa9);
+
+        // Nothing is mapped at $5000 on NROM
+        assert_eq!(
+            cpu.load_at(0x5000, &[0x12, 0x34]),
+            Err(CpuError::ProgramNotWritable { addr: 0x5000 })
+        );
+    }
+
+    #[test]
+    fn test_idle_loops() {
+        // CLC; BCC *; JMP *
+        let mut cpu = CPU::new();
+        cpu.load(vec![0x18, 0x90, 0xfe, 0x4c, 0x03, 0x80]);
+        cpu.reset();
+        assert_eq!(cpu.run_instructions(10), StopReason::Idle { pc: 0x8001 });
+        assert_eq!(cpu.run_for_cycles(100), StopReason::Idle { pc: 0x8001 });
+
+        cpu.program_counter = 0x8003;
+        assert_eq!(cpu.run_instructions(10), StopReason::Idle { pc: 0x8003 });
+    }
+
+    #[test]
+    fn test_jumps_to_itself_that_use_the_stack_are_not_idle() {
+        // JSR *
+        let mut cpu = CPU::new();
+        cpu.load(vec![0x20, 0x00, 0x80]);
+        cpu.reset();
+        assert_eq!(cpu.run_instructions(10), StopReason::BudgetExhausted);
+        assert_eq!(cpu.stack_pointer, STACK_RESET.wrapping_sub(20));
+
+        // BRK with the IRQ vector pointing back at it
+        cpu.load(vec![0x00]);
+        cpu.reset();
+        cpu.poke_u16(IRQ_VECTOR, 0x8000);
+        assert_eq!(cpu.run_instructions(10), StopReason::BudgetExhausted);
+        assert_eq!(cpu.stack_pointer, STACK_RESET.wrapping_sub(30));
+    }
+
+    #[test]
+    fn test_run_returns_once_idle() {
+        // CLI; JMP *, with an NMI handler that counts in $10
+        let mut cpu = CPU::new();
+        cpu.load(vec![0x58, 0x4c, 0x01, 0x80]);
+        cpu.reset();
+        cpu.poke_u16(NMI_VECTOR, 0x9000);
+        cpu.load_at(0x9000, &[0xe6, 0x10, 0x40]).unwrap();
+
+        cpu.trigger_nmi();
+        assert_eq!(cpu.run(), Ok(StopReason::Idle { pc: 0x8001 }));
+        assert_eq!(cpu.mem_read(0x10), 1);
+        assert_eq!(cpu.program_counter, 0x8001);
+
+        // An asserted IRQ keeps interrupting the loop, so run doesn't return
+        // until the handler masks it by leaving I set on the stack
+        cpu.load_at(0x9100, &[0xe6, 0x11, 0x68, 0x09, 0x04, 0x48, 0x40]).unwrap();
+        cpu.poke_u16(IRQ_VECTOR, 0x9100);
+        cpu.set_irq(true);
+        cpu.run().unwrap();
+        assert_eq!(cpu.mem_read(0x11), 1);
+        assert!(cpu.status.contains(CpuFlag::INTERRUPT));
+    }
+
+    #[test]
+    fn test_irq_sources_are_wired_or() {
+        // CLI; NOP; NOP
+        let mut cpu = CPU::new();
+        cpu.load(vec![0x58, 0xea, 0xea]);
+        cpu.reset();
+        cpu.poke_u16(IRQ_VECTOR, 0x9000);
+        cpu.step().unwrap();
+
+        cpu.set_irq_source(IrqSource::MAPPER, true);
+        cpu.set_irq_source(IrqSource::DMC, true);
+        cpu.set_irq_source(IrqSource::MAPPER, false);
+        assert!(cpu.irq_asserted());
+        assert_eq!(cpu.irq_sources(), IrqSource::DMC);
+
+        cpu.set_irq_source(IrqSource::DMC, false);
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+
+        cpu.set_irq_source(IrqSource::FRAME_COUNTER, true);
+        assert_eq!(cpu.step().unwrap().interrupt, Some(Interrupt::Irq));
+        assert_eq!(cpu.program_counter, 0x9000);
+    }
+
+    #[test]
+    fn test_irq_after_cli_and_plp_waits_an_instruction() {
+        // CLI; NOP; NOP; PLP; NOP
+        let mut cpu = CPU::new();
+        cpu.load(vec![0x58, 0xea, 0xea, 0x28, 0xea]);
+        cpu.reset();
+        cpu.poke_u16(IRQ_VECTOR, 0xa000);
+        cpu.set_irq(true);
+
+        assert_eq!(cpu.step().unwrap().instruction.operation, Operation::CLI);
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+        assert_eq!(cpu.step().unwrap().interrupt, Some(Interrupt::Irq));
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0x8002);
+
+        // PLP pulling a clear I flag
+        cpu.set_state(CpuState { pc: 0x8003, sp: 0xfc, p: 0x24, ..cpu.state() });
+        cpu.mem_write(0x01fd, 0x20);
+        assert_eq!(cpu.step().unwrap().instruction.operation, Operation::PLP);
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+        assert_eq!(cpu.step().unwrap().interrupt, Some(Interrupt::Irq));
+    }
+
+    #[test]
+    fn test_soft_reset_keeps_registers() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x42, 0xa2, 0x07, 0xf8, 0x00]);
+        cpu.reset();
+        cpu.run_until_pc(0x8005);
+        cpu.status.remove(CpuFlag::INTERRUPT);
+        let before = cpu.state();
+
+        cpu.soft_reset();
+        let after = cpu.state();
+        assert_eq!((after.a, after.x, after.y), (0x42, 0x07, 0x00));
+        assert_eq!(after.sp, before.sp.wrapping_sub(3));
+        assert_eq!(after.p, before.p | CpuFlag::INTERRUPT.bits());
+        assert_eq!(after.pc, 0x8000);
+        assert_eq!(after.cycles, before.cycles + 7);
+    }
+
+    #[test]
+    fn test_power_on_ram_patterns() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xea]);
+
+        cpu.power_on(RamInit::Ones);
+        assert!((0..0x0800).all(|addr| cpu.mem_read(addr) == 0xff));
+        assert_eq!(cpu.mem_read(0x6000), 0x00);
+        assert_eq!(cpu.mem_read(0x8000), 0xea);
+        assert_eq!(cpu.state().sp, STACK_RESET);
+
+        cpu.power_on(RamInit::Checkerboard);
+        let bytes: Vec<u8> = (0..10).map(|addr| cpu.mem_read(addr)).collect();
+        assert_eq!(bytes, vec![0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00]);
+
+        cpu.power_on(RamInit::Random(1));
+        let first: Vec<u8> = (0..0x0800).map(|addr| cpu.mem_read(addr)).collect();
+        cpu.power_on(RamInit::Random(2));
+        let other: Vec<u8> = (0..0x0800).map(|addr| cpu.mem_read(addr)).collect();
+        cpu.power_on(RamInit::Random(1));
+        let again: Vec<u8> = (0..0x0800).map(|addr| cpu.mem_read(addr)).collect();
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        assert!(first.iter().any(|&byte| byte != first[0]));
+
+        cpu.power_on(RamInit::Zero);
+        assert!((0..0x0800).all(|addr| cpu.mem_read(addr) == 0x00));
+    }
+}
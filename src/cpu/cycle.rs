@@ -0,0 +1,988 @@
+// Cycle-stepped execution: every call to `CPU::tick` performs exactly one bus
+// access, following the per-cycle breakdown in 6502_cpu.txt
+// https://www.nesdev.org/6502_cpu.txt
+use super::*;
+
+/// Progress of the instruction currently being executed by `CPU::tick`
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct MicroState {
+    instruction: Option<&'static Instruction>,
+    interrupt: Option<Interrupt>,
+    opcode: u8,
+    /// Cycle the next tick performs, the opcode fetch being cycle 1
+    cycle: u8,
+    /// Cycle on which the effective address was resolved, 0 while still
+    /// working it out
+    resolved_at: u8,
+    /// Unindexed base address, or the pointer for indirect modes
+    base: u16,
+    addr: u16,
+    data: u8,
+    page_crossed: bool,
+    /// Interrupt lines as sampled at the end of the latest cycle that polls
+    poll: bool,
+    /// Taken branches that stay on the page skip the poll on their second
+    /// cycle, delaying an interrupt by one instruction
+    hold_poll: bool,
+    /// BRK/IRQ switch to the NMI vector when an NMI hijacks them
+    vector: u16,
+    /// Set once the bus pattern of the instruction is done
+    executed: bool,
+    /// Extra internal cycles still to run after `executed`
+    padding: u8,
+    operand_address: Option<u16>,
+    warning: Option<CpuError>,
+    pc_before: u16,
+    cycles_before: u64,
+}
+
+enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+enum AddressStep {
+    Pending,
+    Resolved,
+    /// Indexed reads that stay on the same page read the operand while fixing
+    /// up the address
+    ResolvedWithRead(u8),
+}
+
+fn access(operation: Operation) -> Access {
+    match operation {
+        Operation::STA | Operation::STX | Operation::STY | Operation::STZ | Operation::SAX
+        | Operation::SHA | Operation::SHX | Operation::SHY | Operation::TAS => Access::Write,
+        Operation::ASL | Operation::LSR | Operation::ROL | Operation::ROR | Operation::INC
+        | Operation::DEC | Operation::SLO | Operation::SRE | Operation::RLA | Operation::RRA
+        | Operation::DCP | Operation::ISB | Operation::TRB | Operation::TSB | Operation::RMB
+        | Operation::SMB => Access::ReadModifyWrite,
+        _ => Access::Read,
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    /// Runs a single CPU cycle, performing exactly the one bus read or write
+    /// the hardware does on that cycle, dummy accesses included. Returns the
+    /// `StepResult` on the cycle that completes an instruction or interrupt
+    /// sequence. Like the hardware, the interrupt lines are polled at the end
+    /// of the second to last cycle of each instruction, so an interrupt that
+    /// arrives on the last cycle waits for the instruction after.
+    pub fn tick(&mut self) -> Result<Option<StepResult>, CpuError> {
+        let Some(instruction) = self.micro.instruction else {
+            return self.fetch();
+        };
+
+        let cycle = self.micro.cycle;
+        self.micro.cycle += 1;
+
+        if self.micro.executed {
+            // Internal cycles the 65C02 adds on top of the NMOS bus pattern
+            self.mem_read(self.program_counter);
+            self.micro.padding -= 1;
+        } else {
+            self.micro.executed = self.instruction_cycle(cycle, instruction)?;
+        }
+        self.cycles += 1;
+        self.bus.tick(1);
+
+        if self.micro.executed && self.micro.padding == 0 {
+            return Ok(Some(self.finish()));
+        }
+        if !self.micro.hold_poll {
+            self.micro.poll = self.interrupt_pending();
+        }
+        Ok(None)
+    }
+
+    /// True while `tick` is part way through an instruction
+    pub fn mid_instruction(&self) -> bool {
+        self.micro.instruction.is_some()
+    }
+
+    fn fetch(&mut self) -> Result<Option<StepResult>, CpuError> {
+        let pc = self.program_counter;
+        let code = self.mem_read(pc);
+
+        // An interrupt replaces the fetched opcode with BRK, without
+        // advancing the program counter
+        let interrupt = self.pending_interrupt();
+        let (instruction, warning) = match interrupt {
+            Some(_) => (self.brk_instruction(), None),
+            None => {
+                let instruction = self.decode(code, pc)?;
+                self.program_counter = pc.wrapping_add(1);
+                (instruction, self.decode_warning(instruction, pc))
+            }
+        };
+
+        self.micro = MicroState {
+            instruction: Some(instruction),
+            interrupt,
+            opcode: if interrupt.is_some() { 0x00 } else { code },
+            cycle: 2,
+            padding: self.cmos_extra_cycles(instruction),
+            warning,
+            pc_before: pc,
+            cycles_before: self.cycles,
+            ..MicroState::default()
+        };
+        self.micro.poll = self.interrupt_pending();
+        self.cycles += 1;
+        self.bus.tick(1);
+
+        // The 65C02 single-byte NOPs are done on the fetch
+        if instruction.cycles == 1 {
+            return Ok(Some(self.finish()));
+        }
+        Ok(None)
+    }
+
+    fn finish(&mut self) -> StepResult {
+        let micro = std::mem::take(&mut self.micro);
+        let result = StepResult {
+            opcode: micro.opcode,
+            instruction: micro.instruction.expect("an instruction is in flight"),
+            operand_address: micro.operand_address,
+            cycles: (self.cycles - micro.cycles_before) as u8,
+            pc_before: micro.pc_before,
+            pc_after: self.program_counter,
+            interrupt: micro.interrupt,
+            warning: micro.warning,
+        };
+        self.interrupt_polled = Some(micro.poll);
+        self.masked_at_poll = None;
+        self.call_stack.track(&result, self.stack_pointer);
+        result
+    }
+
+    /// Cycles the 65C02 spends on top of the NMOS bus pattern
+    fn cmos_extra_cycles(&self, instruction: &Instruction) -> u8 {
+        if !self.variant.is_cmos() {
+            return 0;
+        }
+        match (instruction.operation, instruction.mode) {
+            (Operation::ADC | Operation::SBC, _) if self.decimal_mode() => 1,
+            (Operation::JMP, AddressingMode::Indirect) => 1,
+            (Operation::WAI, _) => 1,
+            // $5C reads its absolute operand and then idles
+            (Operation::NOP, AddressingMode::Absolute) => instruction.cycles - 4,
+            _ => 0,
+        }
+    }
+
+    /// Reads the byte at the program counter and steps past it
+    fn fetch_operand(&mut self) -> u8 {
+        let data = self.mem_read(self.program_counter);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        data
+    }
+
+    fn dummy_stack_read(&mut self) {
+        self.mem_read(STACK + self.stack_pointer as u16);
+    }
+
+    /// Performs cycle `cycle` (2 onwards) of `instruction`, returning true on
+    /// its last cycle
+    fn instruction_cycle(&mut self, cycle: u8, instruction: &'static Instruction) -> Result<bool, CpuError> {
+        if self.micro.interrupt.is_some() || instruction.operation == Operation::BRK {
+            return Ok(self.interrupt_cycle(cycle));
+        }
+
+        let done = match instruction.operation {
+            Operation::JSR => self.jsr_cycle(cycle),
+            Operation::RTS => self.rts_cycle(cycle),
+            Operation::RTI => self.rti_cycle(cycle),
+            Operation::JMP => self.jmp_cycle(cycle, instruction.mode),
+            Operation::PHA | Operation::PHP | Operation::PHX | Operation::PHY => {
+                if cycle == 2 {
+                    self.mem_read(self.program_counter);
+                    false
+                } else {
+                    self.execute(instruction)?;
+                    true
+                }
+            }
+            Operation::PLA | Operation::PLP | Operation::PLX | Operation::PLY => match cycle {
+                2 => {
+                    self.mem_read(self.program_counter);
+                    false
+                }
+                3 => {
+                    self.dummy_stack_read();
+                    false
+                }
+                _ => {
+                    self.execute(instruction)?;
+                    true
+                }
+            },
+            Operation::BCC | Operation::BCS | Operation::BEQ | Operation::BMI | Operation::BNE
+            | Operation::BPL | Operation::BVC | Operation::BVS | Operation::BRA => {
+                self.branch_cycle(cycle, self.branch_condition(instruction.operation))
+            }
+            Operation::BBR | Operation::BBS => self.bit_branch_cycle(cycle, instruction.operation),
+            _ => match instruction.mode {
+                AddressingMode::Implied | AddressingMode::Accumulator | AddressingMode::NoneAddressing => {
+                    self.mem_read(self.program_counter);
+                    self.execute(instruction)?;
+                    true
+                }
+                AddressingMode::Immediate => {
+                    self.micro.operand_address = Some(self.program_counter);
+                    let data = self.fetch_operand();
+                    self.execute_read(instruction, data);
+                    true
+                }
+                _ => self.memory_cycle(cycle, instruction),
+            },
+        };
+        Ok(done)
+    }
+
+    /// BRK, NMI and IRQ share the same seven cycle sequence
+    fn interrupt_cycle(&mut self, cycle: u8) -> bool {
+        let interrupt = self.micro.interrupt.unwrap_or(Interrupt::Brk);
+        match cycle {
+            2 => {
+                self.mem_read(self.program_counter);
+                // BRK skips a padding byte, so the return address is PC + 2
+                if interrupt == Interrupt::Brk {
+                    self.program_counter = self.program_counter.wrapping_add(1);
+                }
+            }
+            3 => self.stack_push((self.program_counter >> 8) as u8),
+            4 => self.stack_push(self.program_counter as u8),
+            5 => {
+                // An NMI that arrives before the vector is fetched takes over
+                // a BRK or IRQ, which then run the NMI handler
+                self.micro.vector = interrupt.vector();
+                if interrupt != Interrupt::Nmi && self.nmi_pending {
+                    self.nmi_pending = false;
+                    self.micro.vector = NMI_VECTOR;
+                    if interrupt == Interrupt::Irq {
+                        self.micro.interrupt = Some(Interrupt::Nmi);
+                    }
+                }
+                // The B flag still tells a hijacked BRK apart
+                self.stack_push(self.status_with_break(interrupt == Interrupt::Brk));
+            }
+            6 => {
+                self.micro.data = self.mem_read(self.micro.vector);
+                self.disable_interrupts();
+            }
+            _ => {
+                let hi = self.mem_read(self.micro.vector.wrapping_add(1));
+                self.program_counter = u16::from_le_bytes([self.micro.data, hi]);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn jsr_cycle(&mut self, cycle: u8) -> bool {
+        match cycle {
+            2 => self.micro.data = self.fetch_operand(),
+            3 => self.dummy_stack_read(),
+            // The pushed return address points at the last byte of the JSR
+            4 => self.stack_push((self.program_counter >> 8) as u8),
+            5 => self.stack_push(self.program_counter as u8),
+            _ => {
+                let hi = self.mem_read(self.program_counter);
+                self.program_counter = u16::from_le_bytes([self.micro.data, hi]);
+                self.micro.operand_address = Some(self.program_counter);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn rts_cycle(&mut self, cycle: u8) -> bool {
+        match cycle {
+            2 => {
+                self.mem_read(self.program_counter);
+            }
+            3 => self.dummy_stack_read(),
+            4 => self.micro.data = self.stack_pop(),
+            5 => {
+                let hi = self.stack_pop();
+                self.program_counter = u16::from_le_bytes([self.micro.data, hi]);
+            }
+            _ => {
+                self.fetch_operand();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn rti_cycle(&mut self, cycle: u8) -> bool {
+        match cycle {
+            2 => {
+                self.mem_read(self.program_counter);
+            }
+            3 => self.dummy_stack_read(),
+            4 => self.plp(),
+            5 => self.micro.data = self.stack_pop(),
+            _ => {
+                let hi = self.stack_pop();
+                self.program_counter = u16::from_le_bytes([self.micro.data, hi]);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn jmp_cycle(&mut self, cycle: u8, mode: AddressingMode) -> bool {
+        match (mode, cycle) {
+            (_, 2) => self.micro.data = self.fetch_operand(),
+            (AddressingMode::Absolute, _) => {
+                let hi = self.mem_read(self.program_counter);
+                self.program_counter = u16::from_le_bytes([self.micro.data, hi]);
+                self.micro.operand_address = Some(self.program_counter);
+                return true;
+            }
+            (_, 3) => {
+                let hi = self.fetch_operand();
+                self.micro.base = u16::from_le_bytes([self.micro.data, hi]);
+                if mode == AddressingMode::AbsoluteIndexedIndirect {
+                    self.micro.base = self.micro.base.wrapping_add(self.register_x as u16);
+                }
+            }
+            (AddressingMode::AbsoluteIndexedIndirect, 4) => {
+                self.mem_read(self.program_counter.wrapping_sub(1));
+            }
+            (AddressingMode::Indirect, 4) | (AddressingMode::AbsoluteIndexedIndirect, 5) => {
+                self.micro.data = self.mem_read(self.micro.base);
+            }
+            _ => {
+                let pointer = self.micro.base;
+                // The NMOS parts never carry into the high byte of the pointer
+                let hi_addr = if mode == AddressingMode::Indirect && !self.variant.is_cmos() {
+                    (pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF)
+                } else {
+                    pointer.wrapping_add(1)
+                };
+                let hi = self.mem_read(hi_addr);
+                self.program_counter = u16::from_le_bytes([self.micro.data, hi]);
+                self.micro.operand_address = Some(self.program_counter);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The cycles of a branch from fetching its offset on, with `cycle` 2
+    /// being the offset fetch
+    fn branch_cycle(&mut self, cycle: u8, taken: bool) -> bool {
+        match cycle {
+            2 => {
+                let jump = self.fetch_operand() as i8;
+                self.micro.addr = self.program_counter.wrapping_add(jump as u16);
+                self.micro.operand_address = Some(self.micro.addr);
+                if !taken {
+                    return true;
+                }
+                // Only the page crossing fix-up polls again
+                self.micro.hold_poll = !page_cross(self.program_counter, self.micro.addr);
+                false
+            }
+            3 => {
+                self.mem_read(self.program_counter);
+                let target = self.micro.addr;
+                if !page_cross(self.program_counter, target) {
+                    self.program_counter = target;
+                    return true;
+                }
+                // The low byte is added first, the carry into the high byte
+                // costs another cycle
+                self.program_counter = (self.program_counter & 0xFF00) | (target & 0x00FF);
+                false
+            }
+            _ => {
+                self.mem_read(self.program_counter);
+                self.program_counter = self.micro.addr;
+                true
+            }
+        }
+    }
+
+    /// BBR/BBS read the zero page byte twice, then branch on one of its bits
+    fn bit_branch_cycle(&mut self, cycle: u8, operation: Operation) -> bool {
+        match cycle {
+            2 => self.micro.base = self.fetch_operand() as u16,
+            3 => self.micro.data = self.mem_read(self.micro.base),
+            4 => {
+                self.mem_read(self.micro.base);
+            }
+            _ => {
+                let set = self.micro.data & bit_mask(self.micro.opcode) != 0;
+                return self.branch_cycle(cycle - 3, set == (operation == Operation::BBS));
+            }
+        }
+        false
+    }
+
+    fn memory_cycle(&mut self, cycle: u8, instruction: &Instruction) -> bool {
+        let access = access(instruction.operation);
+
+        if self.micro.resolved_at == 0 {
+            match self.address_cycle(cycle, instruction, &access) {
+                AddressStep::Pending => {}
+                AddressStep::Resolved => {
+                    self.micro.resolved_at = cycle;
+                    self.micro.operand_address = Some(self.micro.addr);
+                }
+                AddressStep::ResolvedWithRead(data) => {
+                    self.micro.operand_address = Some(self.micro.addr);
+                    self.execute_read(instruction, data);
+                    return true;
+                }
+            }
+            return false;
+        }
+
+        let addr = self.micro.addr;
+        match (access, cycle - self.micro.resolved_at) {
+            (Access::Read, _) => {
+                let data = self.mem_read(addr);
+                self.execute_read(instruction, data);
+                true
+            }
+            (Access::Write, _) => {
+                let (addr, data) = self.store_operand(instruction.operation, addr);
+                self.mem_write(addr, data);
+                true
+            }
+            (Access::ReadModifyWrite, 1) => {
+                self.micro.data = self.mem_read(addr);
+                false
+            }
+            (Access::ReadModifyWrite, 2) => {
+                // The NMOS parts write the unmodified value back first
+                if self.variant.is_cmos() {
+                    self.mem_read(addr);
+                } else {
+                    self.mem_write(addr, self.micro.data);
+                }
+                false
+            }
+            (Access::ReadModifyWrite, _) => {
+                let data = self.execute_modify(instruction.operation, self.micro.data);
+                self.mem_write(addr, data);
+                true
+            }
+        }
+    }
+
+    fn address_cycle(&mut self, cycle: u8, instruction: &Instruction, access: &Access) -> AddressStep {
+        let mode = instruction.mode;
+        match (mode, cycle) {
+            (AddressingMode::ZeroPage, _) => {
+                self.micro.addr = self.fetch_operand() as u16;
+                AddressStep::Resolved
+            }
+            (AddressingMode::Absolute, 2) | (AddressingMode::AbsoluteX, 2) | (AddressingMode::AbsoluteY, 2) => {
+                self.micro.data = self.fetch_operand();
+                AddressStep::Pending
+            }
+            (AddressingMode::Absolute, _) => {
+                let hi = self.fetch_operand();
+                self.micro.addr = u16::from_le_bytes([self.micro.data, hi]);
+                AddressStep::Resolved
+            }
+            (AddressingMode::AbsoluteX, 3) | (AddressingMode::AbsoluteY, 3) => {
+                let hi = self.fetch_operand();
+                let index = if mode == AddressingMode::AbsoluteX { self.register_x } else { self.register_y };
+                self.index_base(u16::from_le_bytes([self.micro.data, hi]), index);
+                // The 65C02 shifts and rotates skip the fix-up without a carry
+                let shift = matches!(
+                    instruction.operation,
+                    Operation::ASL | Operation::LSR | Operation::ROL | Operation::ROR
+                );
+                if shift && self.variant.is_cmos() && !self.micro.page_crossed {
+                    return AddressStep::Resolved;
+                }
+                AddressStep::Pending
+            }
+            (AddressingMode::AbsoluteX, _) | (AddressingMode::AbsoluteY, _) | (AddressingMode::IndirectY, 5) => {
+                self.fix_up_index(access)
+            }
+            (_, 2) => {
+                self.micro.base = self.fetch_operand() as u16;
+                AddressStep::Pending
+            }
+            (AddressingMode::ZeroPageX, _) | (AddressingMode::ZeroPageY, _) => {
+                self.mem_read(self.micro.base);
+                let index = if mode == AddressingMode::ZeroPageX { self.register_x } else { self.register_y };
+                self.micro.addr = (self.micro.base as u8).wrapping_add(index) as u16;
+                AddressStep::Resolved
+            }
+            (AddressingMode::IndirectX, 3) => {
+                self.mem_read(self.micro.base);
+                self.micro.base = (self.micro.base as u8).wrapping_add(self.register_x) as u16;
+                AddressStep::Pending
+            }
+            (_, 3) | (AddressingMode::IndirectX, 4) => {
+                self.micro.data = self.mem_read(self.micro.base);
+                AddressStep::Pending
+            }
+            _ => {
+                // Pointers wrap around within the zero page
+                let hi = self.mem_read((self.micro.base as u8).wrapping_add(1) as u16);
+                let addr = u16::from_le_bytes([self.micro.data, hi]);
+                if mode == AddressingMode::IndirectY {
+                    self.index_base(addr, self.register_y);
+                    return AddressStep::Pending;
+                }
+                self.micro.addr = addr;
+                AddressStep::Resolved
+            }
+        }
+    }
+
+    fn index_base(&mut self, base: u16, index: u8) {
+        self.micro.base = base;
+        self.micro.addr = base.wrapping_add(index as u16);
+        self.micro.page_crossed = page_cross(base, self.micro.addr);
+    }
+
+    /// The cycle spent adding the index: the NMOS parts read from the address
+    /// before the carry into the high byte, the 65C02 re-reads the last
+    /// operand byte instead when there is a carry
+    fn fix_up_index(&mut self, access: &Access) -> AddressStep {
+        let unfixed = (self.micro.base & 0xFF00) | (self.micro.addr & 0x00FF);
+        if self.micro.page_crossed && self.variant.is_cmos() {
+            self.mem_read(self.program_counter.wrapping_sub(1));
+            return AddressStep::Resolved;
+        }
+
+        let data = self.mem_read(unfixed);
+        match access {
+            Access::Read if !self.micro.page_crossed => AddressStep::ResolvedWithRead(data),
+            _ => AddressStep::Resolved,
+        }
+    }
+
+    /// The value a write instruction stores, and where it ends up
+    fn store_operand(&mut self, operation: Operation, addr: u16) -> (u16, u8) {
+        let page_crossed = self.micro.page_crossed;
+        match operation {
+            Operation::STA => (addr, self.register_a),
+            Operation::STX => (addr, self.register_x),
+            Operation::STY => (addr, self.register_y),
+            Operation::STZ => (addr, 0),
+            Operation::SAX => (addr, self.register_a & self.register_x),
+            Operation::SHA => high_and_store(addr, self.register_y, self.register_a & self.register_x, page_crossed),
+            Operation::SHX => high_and_store(addr, self.register_y, self.register_x, page_crossed),
+            Operation::SHY => high_and_store(addr, self.register_x, self.register_y, page_crossed),
+            Operation::TAS => {
+                self.stack_pointer = self.register_a & self.register_x;
+                high_and_store(addr, self.register_y, self.stack_pointer, page_crossed)
+            }
+            _ => unreachable!("{:?} is not a store", operation),
+        }
+    }
+
+    /// Applies a read instruction to its operand
+    fn execute_read(&mut self, instruction: &Instruction, data: u8) {
+        match instruction.operation {
+            Operation::ADC => self.add_with_carry(data),
+            Operation::AND => {
+                self.register_a &= data;
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            Operation::BIT => self.test_bits(data, instruction.mode == AddressingMode::Immediate),
+            Operation::CMP => self.compare_value(data, self.register_a),
+            Operation::CPX => self.compare_value(data, self.register_x),
+            Operation::CPY => self.compare_value(data, self.register_y),
+            Operation::EOR => {
+                self.register_a ^= data;
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            Operation::LDA => {
+                self.register_a = data;
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            Operation::LDX => {
+                self.register_x = data;
+                self.update_zero_and_negative_flags(self.register_x);
+            }
+            Operation::LDY => {
+                self.register_y = data;
+                self.update_zero_and_negative_flags(self.register_y);
+            }
+            Operation::ORA => {
+                self.register_a |= data;
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            Operation::SBC => self.subtract_with_borrow(data),
+            Operation::ALR => {
+                self.register_a = self.shift_right(self.register_a & data);
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            Operation::ANC => {
+                self.register_a &= data;
+                self.update_zero_and_negative_flags(self.register_a);
+                self.status.set(CpuFlag::CARRY, self.status.contains(CpuFlag::NEGATIVE));
+            }
+            Operation::ARR => {
+                self.register_a &= data;
+                self.arr_rotate();
+            }
+            Operation::AXS => self.axs_value(data),
+            Operation::LAS => self.las_value(data),
+            // See `lxa` for the magic constant
+            Operation::LAX | Operation::LXA => {
+                self.register_a = data;
+                self.register_x = data;
+                self.update_zero_and_negative_flags(data);
+            }
+            Operation::XAA => self.xaa_value(data),
+            Operation::NOP => {}
+            _ => unreachable!("{:?} is not a read instruction", instruction.operation),
+        }
+    }
+
+    /// Applies a read-modify-write instruction, returning the value to write
+    fn execute_modify(&mut self, operation: Operation, data: u8) -> u8 {
+        let result = match operation {
+            Operation::ASL | Operation::SLO => self.shift_left(data),
+            Operation::LSR | Operation::SRE => self.shift_right(data),
+            Operation::ROL | Operation::RLA => self.rotate_left(data),
+            Operation::ROR | Operation::RRA => self.rotate_right(data),
+            Operation::INC | Operation::ISB => data.wrapping_add(1),
+            Operation::DEC | Operation::DCP => data.wrapping_sub(1),
+            Operation::RMB => return data & !bit_mask(self.micro.opcode),
+            Operation::SMB => return data | bit_mask(self.micro.opcode),
+            Operation::TRB | Operation::TSB => {
+                self.status.set(CpuFlag::ZERO, self.register_a & data == 0);
+                if operation == Operation::TRB {
+                    return data & !self.register_a;
+                }
+                return data | self.register_a;
+            }
+            _ => unreachable!("{:?} is not a read-modify-write instruction", operation),
+        };
+        self.update_zero_and_negative_flags(result);
+
+        match operation {
+            Operation::SLO => {
+                self.register_a |= result;
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            Operation::SRE => {
+                self.register_a ^= result;
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            Operation::RLA => {
+                self.register_a &= result;
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            Operation::RRA => self.add_with_carry(result),
+            Operation::DCP => self.compare_value(result, self.register_a),
+            Operation::ISB => self.subtract_with_borrow(result),
+            _ => {}
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::FlatRam;
+    use crate::cpu_types::IRQ_VECTOR;
+
+    fn run_ticks<B: Bus>(cpu: &mut CPU<B>) -> (StepResult, Vec<u64>) {
+        let mut ticks = vec![];
+        loop {
+            ticks.push(cpu.cycles);
+            if let Some(result) = cpu.tick().unwrap() {
+                return (result, ticks);
+            }
+        }
+    }
+
+    fn cpu_with_program(variant: CpuVariant, program: &[u8]) -> CPU {
+        let mut cpu = CPU::with_variant(variant);
+        cpu.load(program.to_vec());
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn test_tick_matches_step_for_every_opcode() {
+        // Arbitrary but deterministic contents so pointers land all over the place
+        let mut memory = [0u8; 0x10000];
+        for (addr, byte) in memory.iter_mut().enumerate() {
+            *byte = (addr as u8).wrapping_mul(7).wrapping_add((addr >> 8) as u8);
+        }
+
+        for variant in [CpuVariant::Ricoh2A03, CpuVariant::Nmos6502, CpuVariant::Wdc65C02] {
+            for code in 0..=0xFFu8 {
+                let Some(instruction) = decode_table(variant)[code as usize] else {
+                    continue;
+                };
+                if matches!(instruction.operation, Operation::JAM | Operation::STP) {
+                    continue;
+                }
+                for (index, status) in [(0x01, 0b0010_0100), (0xFF, 0b1110_1111), (0x80, 0b0010_1011)] {
+                    let setup = || {
+                        let mut cpu = CPU::with_bus(variant, FlatRam::new());
+                        cpu.bus_mut().as_mut_slice().copy_from_slice(&memory);
+                        cpu.mem_write(0x80F0, code);
+                        cpu.mem_write(0x80F1, 0xFE);
+                        cpu.mem_write(0x80F2, 0x12);
+                        cpu.program_counter = 0x80F0;
+                        cpu.register_a = 0x5A;
+                        cpu.register_x = index;
+                        cpu.register_y = index;
+                        cpu.stack_pointer = 0xF0;
+                        cpu.status = CpuFlag::from_bits_truncate(status);
+                        cpu
+                    };
+                    let mut stepped = setup();
+                    let mut ticked = setup();
+                    let expected = stepped.step().unwrap();
+                    let (result, _) = run_ticks(&mut ticked);
+
+                    let context = format!("{:?} opcode ${:02X} index ${:02X}", variant, code, index);
+                    assert_eq!(result.cycles, expected.cycles, "{}", context);
+                    assert_eq!(result.pc_after, expected.pc_after, "{}", context);
+                    assert_eq!(result.operand_address, expected.operand_address, "{}", context);
+                    assert_eq!(ticked.cycles, stepped.cycles, "{}", context);
+                    assert_eq!(ticked.register_a, stepped.register_a, "{}", context);
+                    assert_eq!(ticked.register_x, stepped.register_x, "{}", context);
+                    assert_eq!(ticked.register_y, stepped.register_y, "{}", context);
+                    assert_eq!(ticked.stack_pointer, stepped.stack_pointer, "{}", context);
+                    assert_eq!(ticked.status, stepped.status, "{}", context);
+                    assert!(ticked.bus().as_slice() == stepped.bus().as_slice(), "{}", context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tick_runs_a_program_like_step() {
+        // LDX #$08; loop: DEX; STX $0200,X; INC $10; BNE loop; JSR sub; BRK
+        // sub: ROL $0201; RTS
+        let program = [
+            0xa2, 0x08, 0xca, 0x9d, 0x00, 0x02, 0xe6, 0x10, 0xd0, 0xf8, 0x20, 0x0e, 0x80, 0x00,
+            0x2e, 0x01, 0x02, 0x60,
+        ];
+        let mut stepped = cpu_with_program(CpuVariant::Ricoh2A03, &program);
+        let mut ticked = cpu_with_program(CpuVariant::Ricoh2A03, &program);
+
+        while stepped.program_counter != 0x800d {
+            let expected = stepped.step().unwrap();
+            let (result, _) = run_ticks(&mut ticked);
+            assert_eq!(result.pc_after, expected.pc_after);
+            assert_eq!(result.cycles, expected.cycles);
+        }
+        assert_eq!(ticked.cycles, stepped.cycles);
+        assert!((0..0x0800).all(|addr| ticked.peek(addr) == stepped.peek(addr)));
+    }
+
+    #[test]
+    fn test_indexed_read_cycles() {
+        // LDA $80F0,X with and without a page cross
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0xbd, 0xf0, 0x80, 0xbd, 0xf0, 0x80]);
+        cpu.register_x = 0x0F;
+        assert_eq!(run_ticks(&mut cpu).0.cycles, 4);
+        cpu.register_x = 0x10;
+        assert_eq!(run_ticks(&mut cpu).0.cycles, 5);
+    }
+
+    #[test]
+    fn test_store_and_read_modify_write_cycles() {
+        // STA $0200,X; INC $0200,X
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0x9d, 0x00, 0x02, 0xfe, 0x00, 0x02]);
+        cpu.register_a = 0x41;
+        assert_eq!(run_ticks(&mut cpu).0.cycles, 5);
+        assert_eq!(run_ticks(&mut cpu).0.cycles, 7);
+        assert_eq!(cpu.mem_read(0x0200), 0x42);
+    }
+
+    #[test]
+    fn test_read_modify_write_writes_twice() {
+        // INC $10: read, write back the old value, then write the new one
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0xe6, 0x10]);
+        cpu.mem_write(0x10, 0x41);
+        let mut seen = vec![];
+        loop {
+            let done = cpu.tick().unwrap().is_some();
+            seen.push(cpu.mem_read(0x10));
+            if done {
+                break;
+            }
+        }
+        assert_eq!(seen, vec![0x41, 0x41, 0x41, 0x41, 0x42]);
+    }
+
+    #[test]
+    fn test_taken_branch_across_page() {
+        // At $80FD: BNE +$10 lands on the next page
+        let mut cpu = CPU::new();
+        cpu.poke(0x80FD, 0xd0);
+        cpu.poke(0x80FE, 0x10);
+        cpu.program_counter = 0x80FD;
+        let (result, ticks) = run_ticks(&mut cpu);
+        assert_eq!(result.cycles, 4);
+        assert_eq!(ticks.len(), 4);
+        assert_eq!(cpu.program_counter, 0x810F);
+    }
+
+    #[test]
+    fn test_nmi_between_ticks() {
+        // LDA $1234; NOP
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0xad, 0x34, 0x12, 0xea]);
+        cpu.poke_u16(NMI_VECTOR, 0x9000);
+        assert!(cpu.tick().unwrap().is_none());
+        cpu.trigger_nmi();
+
+        // LDA finishes before the NMI is taken
+        assert!(cpu.mid_instruction());
+        let (result, _) = run_ticks(&mut cpu);
+        assert_eq!(result.interrupt, None);
+        let (result, ticks) = run_ticks(&mut cpu);
+        assert_eq!(result.interrupt, Some(Interrupt::Nmi));
+        assert_eq!(ticks.len(), 7);
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0x8003);
+    }
+
+    #[test]
+    fn test_nmi_on_the_last_cycle_waits_an_instruction() {
+        // LDA #$01; NOP
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0xa9, 0x01, 0xea]);
+        cpu.poke_u16(NMI_VECTOR, 0x9000);
+        cpu.tick().unwrap();
+        cpu.trigger_nmi();
+
+        assert_eq!(run_ticks(&mut cpu).0.interrupt, None);
+        let (result, _) = run_ticks(&mut cpu);
+        assert_eq!(result.interrupt, None);
+        assert_eq!(result.instruction.operation, Operation::NOP);
+        assert_eq!(run_ticks(&mut cpu).0.interrupt, Some(Interrupt::Nmi));
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0x8003);
+    }
+
+    #[test]
+    fn test_cli_delays_irq_by_one_instruction() {
+        // CLI; NOP; NOP
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0x58, 0xea, 0xea]);
+        cpu.poke_u16(IRQ_VECTOR, 0xa000);
+        cpu.set_irq(true);
+
+        assert_eq!(run_ticks(&mut cpu).0.instruction.operation, Operation::CLI);
+        assert_eq!(run_ticks(&mut cpu).0.interrupt, None);
+        assert_eq!(run_ticks(&mut cpu).0.interrupt, Some(Interrupt::Irq));
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0x8002);
+    }
+
+    #[test]
+    fn test_sei_lets_a_polled_irq_through() {
+        // LDA #$01; SEI; NOP
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0xa9, 0x01, 0x78, 0xea]);
+        cpu.poke_u16(IRQ_VECTOR, 0xa000);
+        cpu.status.remove(CpuFlag::INTERRUPT);
+        // Raised on the last cycle of LDA, so SEI is the first to poll it
+        cpu.tick().unwrap();
+        cpu.set_irq(true);
+        run_ticks(&mut cpu);
+
+        assert_eq!(run_ticks(&mut cpu).0.instruction.operation, Operation::SEI);
+        let (result, _) = run_ticks(&mut cpu);
+        assert_eq!(result.interrupt, Some(Interrupt::Irq));
+        assert_eq!(result.pc_after, 0xa000);
+        // The pushed status already has I set
+        assert_ne!(cpu.mem_read(0x01fb) & CpuFlag::INTERRUPT.bits(), 0);
+    }
+
+    #[test]
+    fn test_taken_branch_delays_irq() {
+        // BNE +1; NOP; NOP
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0xd0, 0x01, 0xea, 0xea]);
+        cpu.poke_u16(IRQ_VECTOR, 0xa000);
+        cpu.status.remove(CpuFlag::INTERRUPT);
+        cpu.tick().unwrap();
+        cpu.set_irq(true);
+
+        // Without a page crossing the branch only polled on its first cycle
+        let (result, ticks) = run_ticks(&mut cpu);
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(result.pc_after, 0x8003);
+        assert_eq!(run_ticks(&mut cpu).0.interrupt, None);
+        assert_eq!(run_ticks(&mut cpu).0.interrupt, Some(Interrupt::Irq));
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0x8004);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0x00, 0x00]);
+        cpu.poke_u16(NMI_VECTOR, 0x9000);
+        cpu.poke_u16(IRQ_VECTOR, 0xa000);
+        cpu.tick().unwrap();
+        cpu.tick().unwrap();
+        cpu.trigger_nmi();
+
+        let (result, _) = run_ticks(&mut cpu);
+        assert_eq!(result.instruction.operation, Operation::BRK);
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(cpu.mem_read_u16(0x01fc), 0x8002);
+        assert_ne!(cpu.mem_read(0x01fb) & CpuFlag::BREAK.bits(), 0);
+        // The NMI was used up by the hijack
+        assert!(!cpu.interrupt_pending());
+    }
+
+    #[test]
+    fn test_nmi_hijacks_irq() {
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0xea]);
+        cpu.poke_u16(NMI_VECTOR, 0x9000);
+        cpu.poke_u16(IRQ_VECTOR, 0xa000);
+        cpu.status.remove(CpuFlag::INTERRUPT);
+        cpu.set_irq(true);
+        cpu.tick().unwrap();
+        cpu.tick().unwrap();
+        cpu.tick().unwrap();
+        cpu.trigger_nmi();
+
+        let (result, _) = run_ticks(&mut cpu);
+        assert_eq!(result.interrupt, Some(Interrupt::Nmi));
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(cpu.mem_read(0x01fb) & CpuFlag::BREAK.bits(), 0);
+    }
+
+    #[test]
+    fn test_step_finishes_an_instruction_started_by_tick() {
+        let mut cpu = cpu_with_program(CpuVariant::Ricoh2A03, &[0xad, 0x34, 0x12, 0xea]);
+        cpu.tick().unwrap();
+        cpu.tick().unwrap();
+        let result = cpu.step().unwrap();
+        assert_eq!(result.pc_before, 0x8000);
+        assert_eq!(result.cycles, 4);
+        assert_eq!(cpu.program_counter, 0x8003);
+    }
+
+    #[test]
+    fn test_65c02_shift_absolute_x_cycles() {
+        // ASL $0200,X; ROR $02F0,X; INC $0200,X
+        let program = [0x1e, 0x00, 0x02, 0x7e, 0xf0, 0x02, 0xfe, 0x00, 0x02];
+        let mut cpu = cpu_with_program(CpuVariant::Wdc65C02, &program);
+        cpu.register_x = 0x10;
+        cpu.mem_write(0x0210, 0x41);
+
+        let (result, ticks) = run_ticks(&mut cpu);
+        assert_eq!((result.cycles, ticks.len()), (6, 6));
+        assert_eq!(cpu.mem_read(0x0210), 0x82);
+        // Crossing into $0300 costs the fix-up cycle
+        assert_eq!(run_ticks(&mut cpu).0.cycles, 7);
+        // INC and DEC keep it either way
+        assert_eq!(run_ticks(&mut cpu).0.cycles, 7);
+
+        let mut nmos = cpu_with_program(CpuVariant::Nmos6502, &program);
+        assert_eq!(run_ticks(&mut nmos).0.cycles, 7);
+    }
+}
@@ -0,0 +1,47 @@
+use std::error::Error;
+use std::fmt;
+use crate::cpu_types::AddressingMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// The byte at `pc` does not decode to any known instruction
+    UnknownOpcode { pc: u16, opcode: u8 },
+    /// An instruction asked for an operand through a mode the CPU can't resolve
+    UnsupportedAddressingMode { pc: u16, mode: AddressingMode },
+    /// A JAM/KIL opcode, or the 65C02 STP, locked up the CPU at `pc`
+    Jammed { pc: u16, opcode: u8 },
+    /// An undocumented opcode was decoded while the policy rejects them
+    UnofficialOpcode { pc: u16, opcode: u8 },
+    /// A program of `len` bytes does not fit in memory when loaded at `addr`
+    ProgramOutOfBounds { addr: u16, len: usize },
+    /// A program byte loaded at `addr` did not stick, as nothing there
+    /// stores it
+    ProgramNotWritable { addr: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            CpuError::UnsupportedAddressingMode { pc, mode } => {
+                write!(f, "addressing mode {:?} not supported at ${:04X}", mode, pc)
+            }
+            CpuError::Jammed { pc, opcode } => {
+                write!(f, "CPU jammed by opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            CpuError::UnofficialOpcode { pc, opcode } => {
+                write!(f, "unofficial opcode ${:02X} rejected at ${:04X}", opcode, pc)
+            }
+            CpuError::ProgramOutOfBounds { addr, len } => {
+                write!(f, "{} byte program does not fit in memory at ${:04X}", len, addr)
+            }
+            CpuError::ProgramNotWritable { addr } => {
+                write!(f, "nothing at ${:04X} holds the program", addr)
+            }
+        }
+    }
+}
+
+impl Error for CpuError {}
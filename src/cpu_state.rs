@@ -0,0 +1,70 @@
+use std::fmt;
+
+/// Snapshot of the CPU registers and cycle count
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuState {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    /// Processor status, as `PHP` would push it minus the B flag
+    pub p: u8,
+    pub cycles: u64,
+}
+
+const FLAG_NAMES: [char; 8] = ['C', 'Z', 'I', 'D', 'B', '-', 'V', 'N'];
+
+impl CpuState {
+    /// Describes every field that differs from `other`, e.g.
+    /// `A:01 -> A:02, P:24 -> P:A6 (+N +Z)`. Empty when the states match.
+    pub fn diff(&self, other: &CpuState) -> String {
+        let mut differences = vec![];
+        let mut register = |name: &str, left: u8, right: u8| {
+            if left != right {
+                differences.push(format!("{name}:{left:02X} -> {name}:{right:02X}"));
+            }
+        };
+        register("A", self.a, other.a);
+        register("X", self.x, other.x);
+        register("Y", self.y, other.y);
+        register("SP", self.sp, other.sp);
+        if self.pc != other.pc {
+            differences.push(format!("PC:{:04X} -> PC:{:04X}", self.pc, other.pc));
+        }
+
+        if self.p != other.p {
+            let flags: Vec<String> = (0..8)
+                .rev()
+                .filter(|bit| (self.p ^ other.p) & (1 << bit) != 0)
+                .map(|bit| {
+                    let sign = if other.p & (1 << bit) != 0 { '+' } else { '-' };
+                    format!("{}{}", sign, FLAG_NAMES[bit])
+                })
+                .collect();
+            differences.push(format!("P:{:02X} -> P:{:02X} ({})", self.p, other.p, flags.join(" ")));
+        }
+        if self.cycles != other.cycles {
+            differences.push(format!("CYC:{} -> CYC:{}", self.cycles, other.cycles));
+        }
+        differences.join(", ")
+    }
+}
+
+/// nestest log style: `C000  A:00 X:00 Y:00 P:24 SP:FD CYC:7`
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04X}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.pc, self.a, self.x, self.y, self.p, self.sp, self.cycles
+        )
+    }
+}
+
+// Same as Display, so failing assert_eq! output reads like a trace log
+impl fmt::Debug for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
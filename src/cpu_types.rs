@@ -1,12 +1,28 @@
+use std::fmt;
 use bitflags::bitflags;
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operation {
     ADC, AND, ASL, BCC, BCS, BEQ, BIT, BMI, BNE, BPL, BRK, BVC, BVS, CLC,
     CLD, CLI, CLV, CMP, CPX, CPY, DEC, DEX, DEY, EOR, INC, INX, INY, JMP,
     JSR, LDA, LDX, LDY, LSR, NOP, ORA, PHA, PHP, PLA, PLP, ROL, ROR, RTI,
     RTS, SBC, SEC, SED, SEI, STA, STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
+    // Unofficial
+    ALR, ANC, ARR, AXS, DCP, ISB, JAM, LAS, LAX, LXA, RLA, RRA, SAX, SHA,
+    SHX, SHY, SLO, SRE, TAS, XAA,
+    // 65C02
+    BBR, BBS, BRA, PHX, PHY, PLX, PLY, RMB, SMB, STP, STZ, TRB, TSB, WAI,
 }
 
+/// The assembler mnemonic, e.g. `LDA`
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AddressingMode {
     Accumulator,
     Immediate,
@@ -20,10 +36,41 @@ pub enum AddressingMode {
     Indirect,
     IndirectX,
     IndirectY,
+    /// 65C02 `(zp)`
+    ZeroPageIndirect,
+    /// 65C02 `(abs,X)`, only used by JMP
+    AbsoluteIndexedIndirect,
+    /// 65C02 `zp,rel`, only used by BBR/BBS
+    ZeroPageRelative,
     Relative,
     NoneAddressing,
 }
 
+impl AddressingMode {
+    /// Number of operand bytes following the opcode
+    pub const fn operand_bytes(&self) -> u8 {
+        match self {
+            AddressingMode::Accumulator
+            | AddressingMode::Implied
+            | AddressingMode::NoneAddressing => 0,
+            AddressingMode::Immediate
+            | AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageX
+            | AddressingMode::ZeroPageY
+            | AddressingMode::IndirectX
+            | AddressingMode::IndirectY
+            | AddressingMode::ZeroPageIndirect
+            | AddressingMode::Relative => 1,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect
+            | AddressingMode::AbsoluteIndexedIndirect
+            | AddressingMode::ZeroPageRelative => 2,
+        }
+    }
+}
+
 bitflags! {
     /// # Status Register (P) http://wiki.nesdev.com/w/index.php/Status_flags
     ///
@@ -37,6 +84,7 @@ bitflags! {
     ///  | +--------------- Overflow Flag
     ///  +----------------- Negative Flag
     ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct CpuFlag: u8 {
         const CARRY             = 0b00000001;
         const ZERO              = 0b00000010;
@@ -47,7 +95,114 @@ bitflags! {
         const OVERFLOW          = 0b01000000;
         const NEGATIVE          = 0b10000000;
     }
+
+    /// Devices that can pull the shared, wired-OR IRQ line low. The CPU sees
+    /// an IRQ while any of them holds it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IrqSource: u8 {
+        const MAPPER            = 0b00000001;
+        const FRAME_COUNTER     = 0b00000010;
+        const DMC               = 0b00000100;
+        const EXPANSION_AUDIO   = 0b00001000;
+        /// Anything else, e.g. a test harness
+        const EXTERNAL          = 0b00010000;
+    }
+}
+
+/// Which chip the core emulates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CpuVariant {
+    /// The NES CPU: an NMOS 6502 with decimal mode disconnected
+    #[default]
+    Ricoh2A03,
+    /// A stock NMOS 6502 with working BCD arithmetic
+    Nmos6502,
+    /// The CMOS 65C02 with its extra instructions and bug fixes
+    Wdc65C02,
+}
+
+impl CpuVariant {
+    pub fn has_decimal_mode(&self) -> bool {
+        match self {
+            CpuVariant::Ricoh2A03 => false,
+            CpuVariant::Nmos6502 | CpuVariant::Wdc65C02 => true,
+        }
+    }
+
+    pub fn is_cmos(&self) -> bool {
+        *self == CpuVariant::Wdc65C02
+    }
+}
+
+/// What the CPU does when it decodes an undocumented opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnofficialOpcodePolicy {
+    /// Run it like the hardware would
+    #[default]
+    Execute,
+    /// Run it, but report it in `StepResult::warning`
+    Warn,
+    /// Stop with `CpuError::UnofficialOpcode`
+    Reject,
+}
+
+/// What the internal RAM holds after `CPU::power_on`. The real contents are
+/// indeterminate, so software that depends on them is buggy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RamInit {
+    #[default]
+    Zero,
+    /// Every byte $FF
+    Ones,
+    /// Four bytes of $00, then four of $FF, as many consoles come up
+    Checkerboard,
+    /// Pseudo-random bytes, the same for the same seed
+    Random(u64),
+}
+
+impl RamInit {
+    pub fn fill(&self, ram: &mut [u8]) {
+        match *self {
+            RamInit::Zero => ram.fill(0x00),
+            RamInit::Ones => ram.fill(0xff),
+            RamInit::Checkerboard => {
+                for (addr, byte) in ram.iter_mut().enumerate() {
+                    *byte = if addr & 0x04 == 0 { 0x00 } else { 0xff };
+                }
+            }
+            RamInit::Random(seed) => {
+                // xorshift64, which gets stuck on a zero state
+                let mut state = if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed };
+                for byte in ram.iter_mut() {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = (state >> 32) as u8;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+    Brk,
+}
+
+impl Interrupt {
+    pub fn vector(&self) -> u16 {
+        match self {
+            Interrupt::Nmi => NMI_VECTOR,
+            Interrupt::Irq | Interrupt::Brk => IRQ_VECTOR,
+        }
+    }
 }
 
 pub const STACK: u16 = 0x0100;
 pub const STACK_RESET: u8 = 0xfd;
+
+pub const NMI_VECTOR: u16 = 0xFFFA;
+pub const RESET_VECTOR: u16 = 0xFFFC;
+pub const IRQ_VECTOR: u16 = 0xFFFE;
@@ -1,177 +1,661 @@
-use std::collections::HashMap;
-use lazy_static::lazy_static;
-use crate::cpu_types::{AddressingMode, Operation};
+use crate::cpu_types::{AddressingMode, CpuVariant, Operation};
+
+#[derive(Debug)]
 pub struct Instruction {
     pub code: u8,
     pub operation: Operation,
     pub mode: AddressingMode,
     pub length: u8,
     pub cycles: u8,
+    /// False for the undocumented NMOS opcodes
+    pub official: bool,
 }
 
 impl Instruction {
-    fn new(code: u8, operation: Operation, mode: AddressingMode, length: u8, cycles: u8) -> Self {
+    const fn new(code: u8, operation: Operation, mode: AddressingMode, length: u8, cycles: u8) -> Self {
         Instruction {
             code,
             operation,
             mode,
             length, // in bytes
             cycles,
+            official: true,
+        }
+    }
+
+    const fn unofficial(code: u8, operation: Operation, mode: AddressingMode, length: u8, cycles: u8) -> Self {
+        Instruction {
+            official: false,
+            ..Instruction::new(code, operation, mode, length, cycles)
         }
     }
 }
 
-lazy_static! {
-    pub static ref INSTRUCTIONS: Vec<Instruction> = vec![
-        Instruction::new(0x69, Operation::ADC, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0x65, Operation::ADC, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0x75, Operation::ADC, AddressingMode::ZeroPageX, 2, 4),
-        Instruction::new(0x6d, Operation::ADC, AddressingMode::Absolute, 3, 4),
-        Instruction::new(0x7d, Operation::ADC, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
-        Instruction::new(0x79, Operation::ADC, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
-        Instruction::new(0x61, Operation::ADC, AddressingMode::IndirectX, 2, 6),
-        Instruction::new(0x71, Operation::ADC, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+/// Every NMOS opcode, official and undocumented
+pub static INSTRUCTIONS: &[Instruction] = &[
+    Instruction::new(0x69, Operation::ADC, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x65, Operation::ADC, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x75, Operation::ADC, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x6d, Operation::ADC, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0x7d, Operation::ADC, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::new(0x79, Operation::ADC, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::new(0x61, Operation::ADC, AddressingMode::IndirectX, 2, 6),
+    Instruction::new(0x71, Operation::ADC, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+
+    Instruction::new(0x29, Operation::AND, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x25, Operation::AND, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x35, Operation::AND, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x2d, Operation::AND, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0x3d, Operation::AND, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::new(0x39, Operation::AND, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::new(0x21, Operation::AND, AddressingMode::IndirectX, 2, 6),
+    Instruction::new(0x31, Operation::AND, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+
+    Instruction::new(0x0a, Operation::ASL, AddressingMode::Accumulator, 1, 2),
+    Instruction::new(0x06, Operation::ASL, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x16, Operation::ASL, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::new(0x0e, Operation::ASL, AddressingMode::Absolute, 3, 6),
+    Instruction::new(0x1e, Operation::ASL, AddressingMode::AbsoluteX, 3, 7),
+
+    Instruction::new(0x90, Operation::BCC, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+
+    Instruction::new(0xb0, Operation::BCS, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+
+    Instruction::new(0x30, Operation::BMI, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+
+    Instruction::new(0xd0, Operation::BNE, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+
+    Instruction::new(0x10, Operation::BPL, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+
+    Instruction::new(0xf0, Operation::BEQ, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
 
-        Instruction::new(0x29, Operation::AND, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0x25, Operation::AND, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0x35, Operation::AND, AddressingMode::ZeroPageX, 2, 4),
-        Instruction::new(0x2d, Operation::AND, AddressingMode::Absolute, 3, 4),
-        Instruction::new(0x3d, Operation::AND, AddressingMode::AbsoluteX, 3, 4),
-        Instruction::new(0x39, Operation::AND, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
-        Instruction::new(0x21, Operation::AND, AddressingMode::IndirectX, 2, 6), // +1 if page crossed
-        Instruction::new(0x31, Operation::AND, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+    Instruction::new(0x24, Operation::BIT, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x2c, Operation::BIT, AddressingMode::Absolute, 3, 4),
 
-        Instruction::new(0x0a, Operation::ASL, AddressingMode::Accumulator, 1, 2),
-        Instruction::new(0x06, Operation::ASL, AddressingMode::ZeroPage, 2, 5),
-        Instruction::new(0x16, Operation::ASL, AddressingMode::ZeroPageX, 2, 6),
-        Instruction::new(0x0e, Operation::ASL, AddressingMode::Absolute, 3, 6),
-        Instruction::new(0x1e, Operation::ASL, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::new(0x00, Operation::BRK, AddressingMode::Implied, 1, 7),
 
-        Instruction::new(0x90, Operation::BCC, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x50, Operation::BVC, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
 
-        Instruction::new(0xb0, Operation::BCS, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x70, Operation::BVS, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
 
-        Instruction::new(0xF0, Operation::BMI, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x18, Operation::CLC, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0xF0, Operation::BNE, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xd8, Operation::CLD, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0xF0, Operation::BPL, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x58, Operation::CLI, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0xF0, Operation::BEQ, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xb8, Operation::CLV, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0x24, Operation::BIT, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0x2c, Operation::BIT, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0xc9, Operation::CMP, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xc5, Operation::CMP, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0xd5, Operation::CMP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0xcd, Operation::CMP, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0xdd, Operation::CMP, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::new(0xd9, Operation::CMP, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::new(0xc1, Operation::CMP, AddressingMode::IndirectX, 2, 6),
+    Instruction::new(0xd1, Operation::CMP, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
 
-        Instruction::new(0x00, Operation::BRK, AddressingMode::Implied, 1, 7),
+    Instruction::new(0xe0, Operation::CPX, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xe4, Operation::CPX, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0xec, Operation::CPX, AddressingMode::Absolute, 3, 4),
 
-        Instruction::new(0xF0, Operation::BVC, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xc0, Operation::CPY, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xc4, Operation::CPY, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0xcc, Operation::CPY, AddressingMode::Absolute, 3, 4),
 
-        Instruction::new(0xF0, Operation::BVS, AddressingMode::Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xc6, Operation::DEC, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0xd6, Operation::DEC, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::new(0xce, Operation::DEC, AddressingMode::Absolute, 3, 6),
+    Instruction::new(0xde, Operation::DEC, AddressingMode::AbsoluteX, 3, 7),
 
-        Instruction::new(0x18, Operation::CLC, AddressingMode::Implied, 1, 2),
+    Instruction::new(0xca, Operation::DEX, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0xd8, Operation::CLD, AddressingMode::Implied, 1, 2),
+    Instruction::new(0x88, Operation::DEY, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0x58, Operation::CLI, AddressingMode::Implied, 1, 2),
+    Instruction::new(0x49, Operation::EOR, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x45, Operation::EOR, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x55, Operation::EOR, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x4d, Operation::EOR, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0x5d, Operation::EOR, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::new(0x59, Operation::EOR, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::new(0x41, Operation::EOR, AddressingMode::IndirectX, 2, 6),
+    Instruction::new(0x51, Operation::EOR, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
 
-        Instruction::new(0xb8, Operation::CLV, AddressingMode::Implied, 1, 2),
+    Instruction::new(0xe6, Operation::INC, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0xf6, Operation::INC, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::new(0xee, Operation::INC, AddressingMode::Absolute, 3, 6),
+    Instruction::new(0xfe, Operation::INC, AddressingMode::AbsoluteX, 3, 7),
 
-        Instruction::new(0xc9, Operation::CMP, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0xc5, Operation::CMP, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0xd5, Operation::CMP, AddressingMode::ZeroPageX, 2, 4),
-        Instruction::new(0xcd, Operation::CMP, AddressingMode::Absolute, 3, 4),
-        Instruction::new(0xdd, Operation::CMP, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
-        Instruction::new(0xd9, Operation::CMP, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
-        Instruction::new(0xc1, Operation::CMP, AddressingMode::IndirectX, 2, 6),
-        Instruction::new(0xd1, Operation::CMP, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+    Instruction::new(0xe8, Operation::INX, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0xe0, Operation::CPX, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0xe4, Operation::CPX, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0xec, Operation::CPX, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0xc8, Operation::INY, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0xc0, Operation::CPY, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0xc4, Operation::CPY, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0xcc, Operation::CPY, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0x4c, Operation::JMP, AddressingMode::Absolute, 3, 3),
+    Instruction::new(0x6c, Operation::JMP, AddressingMode::Indirect, 3, 5),
 
-        Instruction::new(0xc9, Operation::DEC, AddressingMode::ZeroPage, 2, 5),
-        Instruction::new(0xd6, Operation::DEC, AddressingMode::ZeroPageX, 2, 6),
-        Instruction::new(0xce, Operation::DEC, AddressingMode::Absolute, 3, 6),
-        Instruction::new(0xde, Operation::DEC, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::new(0x20, Operation::JSR, AddressingMode::Absolute, 3, 6),
 
-        Instruction::new(0xca, Operation::DEX, AddressingMode::Implied, 1, 2),
+    Instruction::new(0xa9, Operation::LDA, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xa5, Operation::LDA, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0xb5, Operation::LDA, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0xad, Operation::LDA, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0xbd, Operation::LDA, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::new(0xb9, Operation::LDA, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::new(0xa1, Operation::LDA, AddressingMode::IndirectX, 2, 6),
+    Instruction::new(0xb1, Operation::LDA, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
 
-        Instruction::new(0x88, Operation::DEY, AddressingMode::Implied, 1, 2),
+    Instruction::new(0xa2, Operation::LDX, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xa6, Operation::LDX, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0xb6, Operation::LDX, AddressingMode::ZeroPageY, 2, 4),
+    Instruction::new(0xae, Operation::LDX, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0xbe, Operation::LDX, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
 
-        Instruction::new(0x49, Operation::EOR, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0x45, Operation::EOR, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0x55, Operation::EOR, AddressingMode::ZeroPageX, 2, 4),
-        Instruction::new(0x4d, Operation::EOR, AddressingMode::Absolute, 3, 4),
-        Instruction::new(0x5d, Operation::EOR, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
-        Instruction::new(0x59, Operation::EOR, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
-        Instruction::new(0x41, Operation::EOR, AddressingMode::IndirectX, 2, 6),
-        Instruction::new(0x51, Operation::EOR, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+    Instruction::new(0xa0, Operation::LDY, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xa4, Operation::LDY, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0xb4, Operation::LDY, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0xac, Operation::LDY, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0xbc, Operation::LDY, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
 
-        Instruction::new(0xe6, Operation::INC, AddressingMode::ZeroPage, 2, 5),
-        Instruction::new(0xf6, Operation::INC, AddressingMode::ZeroPageX, 2, 6),
-        Instruction::new(0xee, Operation::INC, AddressingMode::Absolute, 3, 6),
-        Instruction::new(0xfe, Operation::INC, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::new(0x4a, Operation::LSR, AddressingMode::Accumulator, 1, 2),
+    Instruction::new(0x46, Operation::LSR, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x56, Operation::LSR, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::new(0x4e, Operation::LSR, AddressingMode::Absolute, 3, 6),
+    Instruction::new(0x5e, Operation::LSR, AddressingMode::AbsoluteX, 3, 7),
 
-        Instruction::new(0xe8, Operation::INX, AddressingMode::Implied, 1, 2),
+    Instruction::new(0xea, Operation::NOP, AddressingMode::Implied, 1, 2),
 
-        Instruction::new(0xc8, Operation::INY, AddressingMode::Implied, 1, 2),
+    Instruction::new(0x09, Operation::ORA, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x05, Operation::ORA, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x15, Operation::ORA, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x0d, Operation::ORA, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0x1d, Operation::ORA, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::new(0x19, Operation::ORA, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::new(0x01, Operation::ORA, AddressingMode::IndirectX, 2, 6),
+    Instruction::new(0x11, Operation::ORA, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
 
-        Instruction::new(0x4c, Operation::JMP, AddressingMode::Absolute, 3, 3),
-        Instruction::new(0x6c, Operation::JMP, AddressingMode::Indirect, 3, 5),
-
-        Instruction::new(0x20, Operation::JSR, AddressingMode::Absolute, 3, 6),
+    Instruction::new(0x48, Operation::PHA, AddressingMode::Implied, 1, 3),
 
-        Instruction::new(0xa9, Operation::LDA, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0xa5, Operation::LDA, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0xb5, Operation::LDA, AddressingMode::ZeroPageX, 2, 4),
-        Instruction::new(0xad, Operation::LDA, AddressingMode::Absolute, 3, 4),
-        Instruction::new(0xbd, Operation::LDA, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
-        Instruction::new(0xb9, Operation::LDA, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
-        Instruction::new(0xa1, Operation::LDA, AddressingMode::IndirectX, 2, 6),
-        Instruction::new(0xb1, Operation::LDA, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+    Instruction::new(0x08, Operation::PHP, AddressingMode::Implied, 1, 3),
 
-        Instruction::new(0xa2, Operation::LDX, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0xa6, Operation::LDX, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0xb6, Operation::LDX, AddressingMode::ZeroPageY, 2, 4),
-        Instruction::new(0xae, Operation::LDX, AddressingMode::Absolute, 3, 4),
-        Instruction::new(0xbe, Operation::LDX, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::new(0x68, Operation::PLA, AddressingMode::Implied, 1, 4),
 
-        Instruction::new(0xa0, Operation::LDX, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0xa4, Operation::LDX, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0xb4, Operation::LDX, AddressingMode::ZeroPageX, 2, 4),
-        Instruction::new(0xac, Operation::LDX, AddressingMode::Absolute, 3, 4),
-        Instruction::new(0xbc, Operation::LDX, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::new(0x28, Operation::PLP, AddressingMode::Implied, 1, 4),
 
-        Instruction::new(0x4a, Operation::LSR, AddressingMode::Accumulator, 1, 2),
-        Instruction::new(0x46, Operation::LSR, AddressingMode::ZeroPage, 2, 5),
-        Instruction::new(0x56, Operation::LSR, AddressingMode::ZeroPageX, 2, 6),
-        Instruction::new(0x4e, Operation::LSR, AddressingMode::Absolute, 3, 6),
-        Instruction::new(0x5e, Operation::LSR, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::new(0x2a, Operation::ROL, AddressingMode::Accumulator, 1, 2),
+    Instruction::new(0x26, Operation::ROL, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x36, Operation::ROL, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::new(0x2e, Operation::ROL, AddressingMode::Absolute, 3, 6),
+    Instruction::new(0x3e, Operation::ROL, AddressingMode::AbsoluteX, 3, 7),
+
+    Instruction::new(0x6a, Operation::ROR, AddressingMode::Accumulator, 1, 2),
+    Instruction::new(0x66, Operation::ROR, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x76, Operation::ROR, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::new(0x6e, Operation::ROR, AddressingMode::Absolute, 3, 6),
+    Instruction::new(0x7e, Operation::ROR, AddressingMode::AbsoluteX, 3, 7),
 
-        Instruction::new(0xea, Operation::NOP, AddressingMode::Implied, 1, 2),
+    Instruction::new(0x40, Operation::RTI, AddressingMode::Implied, 1, 6),
 
-        Instruction::new(0x09, Operation::ORA, AddressingMode::Immediate, 2, 2),
-        Instruction::new(0x05, Operation::ORA, AddressingMode::ZeroPage, 2, 3),
-        Instruction::new(0x15, Operation::ORA, AddressingMode::ZeroPageX, 2, 4),
-        Instruction::new(0x0d, Operation::ORA, AddressingMode::Absolute, 3, 4),
-        Instruction::new(0x1d, Operation::ORA, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
-        Instruction::new(0x19, Operation::ORA, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
-        Instruction::new(0x01, Operation::ORA, AddressingMode::IndirectX, 2, 6),
-        Instruction::new(0x11, Operation::ORA, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
-
-        Instruction::new(0xaa, Operation::TAX, AddressingMode::NoneAddressing, 1, 2),
-    ];
-
-    pub static ref INSTRUCTIONS_MAP: HashMap<u8, &'static Instruction> = {
-        let mut map = HashMap::new();
-        for instr in &*INSTRUCTIONS {
-            map.insert(instr.code, instr);
+    Instruction::new(0x60, Operation::RTS, AddressingMode::Implied, 1, 6),
+
+    Instruction::new(0xe9, Operation::SBC, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xe5, Operation::SBC, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0xf5, Operation::SBC, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0xed, Operation::SBC, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0xfd, Operation::SBC, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::new(0xf9, Operation::SBC, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::new(0xe1, Operation::SBC, AddressingMode::IndirectX, 2, 6),
+    Instruction::new(0xf1, Operation::SBC, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+
+    Instruction::new(0x38, Operation::SEC, AddressingMode::Implied, 1, 2),
+
+    Instruction::new(0xf8, Operation::SED, AddressingMode::Implied, 1, 2),
+
+    Instruction::new(0x78, Operation::SEI, AddressingMode::Implied, 1, 2),
+
+    Instruction::new(0x85, Operation::STA, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x95, Operation::STA, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x8d, Operation::STA, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0x9d, Operation::STA, AddressingMode::AbsoluteX, 3, 5),
+    Instruction::new(0x99, Operation::STA, AddressingMode::AbsoluteY, 3, 5),
+    Instruction::new(0x81, Operation::STA, AddressingMode::IndirectX, 2, 6),
+    Instruction::new(0x91, Operation::STA, AddressingMode::IndirectY, 2, 6),
+
+    Instruction::new(0x86, Operation::STX, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x96, Operation::STX, AddressingMode::ZeroPageY, 2, 4),
+    Instruction::new(0x8e, Operation::STX, AddressingMode::Absolute, 3, 4),
+
+    Instruction::new(0x84, Operation::STY, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x94, Operation::STY, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x8c, Operation::STY, AddressingMode::Absolute, 3, 4),
+
+    Instruction::new(0xaa, Operation::TAX, AddressingMode::NoneAddressing, 1, 2),
+
+    Instruction::new(0xa8, Operation::TAY, AddressingMode::Implied, 1, 2),
+
+    Instruction::new(0xba, Operation::TSX, AddressingMode::Implied, 1, 2),
+
+    Instruction::new(0x8a, Operation::TXA, AddressingMode::Implied, 1, 2),
+
+    Instruction::new(0x9a, Operation::TXS, AddressingMode::Implied, 1, 2),
+
+    Instruction::new(0x98, Operation::TYA, AddressingMode::Implied, 1, 2),
+
+    // Unofficial opcodes
+    // https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+
+    Instruction::unofficial(0x4b, Operation::ALR, AddressingMode::Immediate, 2, 2),
+
+    Instruction::unofficial(0x0b, Operation::ANC, AddressingMode::Immediate, 2, 2),
+    Instruction::unofficial(0x2b, Operation::ANC, AddressingMode::Immediate, 2, 2),
+
+    Instruction::unofficial(0x6b, Operation::ARR, AddressingMode::Immediate, 2, 2),
+
+    Instruction::unofficial(0xcb, Operation::AXS, AddressingMode::Immediate, 2, 2),
+
+    Instruction::unofficial(0xc7, Operation::DCP, AddressingMode::ZeroPage, 2, 5),
+    Instruction::unofficial(0xd7, Operation::DCP, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::unofficial(0xcf, Operation::DCP, AddressingMode::Absolute, 3, 6),
+    Instruction::unofficial(0xdf, Operation::DCP, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::unofficial(0xdb, Operation::DCP, AddressingMode::AbsoluteY, 3, 7),
+    Instruction::unofficial(0xc3, Operation::DCP, AddressingMode::IndirectX, 2, 8),
+    Instruction::unofficial(0xd3, Operation::DCP, AddressingMode::IndirectY, 2, 8),
+
+    Instruction::unofficial(0xe7, Operation::ISB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::unofficial(0xf7, Operation::ISB, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::unofficial(0xef, Operation::ISB, AddressingMode::Absolute, 3, 6),
+    Instruction::unofficial(0xff, Operation::ISB, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::unofficial(0xfb, Operation::ISB, AddressingMode::AbsoluteY, 3, 7),
+    Instruction::unofficial(0xe3, Operation::ISB, AddressingMode::IndirectX, 2, 8),
+    Instruction::unofficial(0xf3, Operation::ISB, AddressingMode::IndirectY, 2, 8),
+
+    Instruction::unofficial(0x02, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x12, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x22, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x32, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x42, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x52, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x62, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x72, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x92, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0xb2, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0xd2, Operation::JAM, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0xf2, Operation::JAM, AddressingMode::Implied, 1, 2),
+
+    Instruction::unofficial(0xbb, Operation::LAS, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+
+    Instruction::unofficial(0xa7, Operation::LAX, AddressingMode::ZeroPage, 2, 3),
+    Instruction::unofficial(0xb7, Operation::LAX, AddressingMode::ZeroPageY, 2, 4),
+    Instruction::unofficial(0xaf, Operation::LAX, AddressingMode::Absolute, 3, 4),
+    Instruction::unofficial(0xbf, Operation::LAX, AddressingMode::AbsoluteY, 3, 4), // +1 if page crossed
+    Instruction::unofficial(0xa3, Operation::LAX, AddressingMode::IndirectX, 2, 6),
+    Instruction::unofficial(0xb3, Operation::LAX, AddressingMode::IndirectY, 2, 5), // +1 if page crossed
+
+    Instruction::unofficial(0xab, Operation::LXA, AddressingMode::Immediate, 2, 2),
+
+    Instruction::unofficial(0x1a, Operation::NOP, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x3a, Operation::NOP, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x5a, Operation::NOP, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x7a, Operation::NOP, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0xda, Operation::NOP, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0xfa, Operation::NOP, AddressingMode::Implied, 1, 2),
+    Instruction::unofficial(0x80, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::unofficial(0x82, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::unofficial(0x89, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::unofficial(0xc2, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::unofficial(0xe2, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::unofficial(0x04, Operation::NOP, AddressingMode::ZeroPage, 2, 3),
+    Instruction::unofficial(0x44, Operation::NOP, AddressingMode::ZeroPage, 2, 3),
+    Instruction::unofficial(0x64, Operation::NOP, AddressingMode::ZeroPage, 2, 3),
+    Instruction::unofficial(0x14, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::unofficial(0x34, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::unofficial(0x54, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::unofficial(0x74, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::unofficial(0xd4, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::unofficial(0xf4, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::unofficial(0x0c, Operation::NOP, AddressingMode::Absolute, 3, 4),
+    Instruction::unofficial(0x1c, Operation::NOP, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::unofficial(0x3c, Operation::NOP, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::unofficial(0x5c, Operation::NOP, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::unofficial(0x7c, Operation::NOP, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::unofficial(0xdc, Operation::NOP, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+    Instruction::unofficial(0xfc, Operation::NOP, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+
+    Instruction::unofficial(0x27, Operation::RLA, AddressingMode::ZeroPage, 2, 5),
+    Instruction::unofficial(0x37, Operation::RLA, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::unofficial(0x2f, Operation::RLA, AddressingMode::Absolute, 3, 6),
+    Instruction::unofficial(0x3f, Operation::RLA, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::unofficial(0x3b, Operation::RLA, AddressingMode::AbsoluteY, 3, 7),
+    Instruction::unofficial(0x23, Operation::RLA, AddressingMode::IndirectX, 2, 8),
+    Instruction::unofficial(0x33, Operation::RLA, AddressingMode::IndirectY, 2, 8),
+
+    Instruction::unofficial(0x67, Operation::RRA, AddressingMode::ZeroPage, 2, 5),
+    Instruction::unofficial(0x77, Operation::RRA, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::unofficial(0x6f, Operation::RRA, AddressingMode::Absolute, 3, 6),
+    Instruction::unofficial(0x7f, Operation::RRA, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::unofficial(0x7b, Operation::RRA, AddressingMode::AbsoluteY, 3, 7),
+    Instruction::unofficial(0x63, Operation::RRA, AddressingMode::IndirectX, 2, 8),
+    Instruction::unofficial(0x73, Operation::RRA, AddressingMode::IndirectY, 2, 8),
+
+    Instruction::unofficial(0x87, Operation::SAX, AddressingMode::ZeroPage, 2, 3),
+    Instruction::unofficial(0x97, Operation::SAX, AddressingMode::ZeroPageY, 2, 4),
+    Instruction::unofficial(0x8f, Operation::SAX, AddressingMode::Absolute, 3, 4),
+    Instruction::unofficial(0x83, Operation::SAX, AddressingMode::IndirectX, 2, 6),
+
+    Instruction::unofficial(0xeb, Operation::SBC, AddressingMode::Immediate, 2, 2),
+
+    Instruction::unofficial(0x9f, Operation::SHA, AddressingMode::AbsoluteY, 3, 5),
+    Instruction::unofficial(0x93, Operation::SHA, AddressingMode::IndirectY, 2, 6),
+
+    Instruction::unofficial(0x9e, Operation::SHX, AddressingMode::AbsoluteY, 3, 5),
+
+    Instruction::unofficial(0x9c, Operation::SHY, AddressingMode::AbsoluteX, 3, 5),
+
+    Instruction::unofficial(0x07, Operation::SLO, AddressingMode::ZeroPage, 2, 5),
+    Instruction::unofficial(0x17, Operation::SLO, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::unofficial(0x0f, Operation::SLO, AddressingMode::Absolute, 3, 6),
+    Instruction::unofficial(0x1f, Operation::SLO, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::unofficial(0x1b, Operation::SLO, AddressingMode::AbsoluteY, 3, 7),
+    Instruction::unofficial(0x03, Operation::SLO, AddressingMode::IndirectX, 2, 8),
+    Instruction::unofficial(0x13, Operation::SLO, AddressingMode::IndirectY, 2, 8),
+
+    Instruction::unofficial(0x47, Operation::SRE, AddressingMode::ZeroPage, 2, 5),
+    Instruction::unofficial(0x57, Operation::SRE, AddressingMode::ZeroPageX, 2, 6),
+    Instruction::unofficial(0x4f, Operation::SRE, AddressingMode::Absolute, 3, 6),
+    Instruction::unofficial(0x5f, Operation::SRE, AddressingMode::AbsoluteX, 3, 7),
+    Instruction::unofficial(0x5b, Operation::SRE, AddressingMode::AbsoluteY, 3, 7),
+    Instruction::unofficial(0x43, Operation::SRE, AddressingMode::IndirectX, 2, 8),
+    Instruction::unofficial(0x53, Operation::SRE, AddressingMode::IndirectY, 2, 8),
+
+    Instruction::unofficial(0x9b, Operation::TAS, AddressingMode::AbsoluteY, 3, 5),
+
+    Instruction::unofficial(0x8b, Operation::XAA, AddressingMode::Immediate, 2, 2),
+];
+
+/// WDC 65C02 additions and changes on top of the official NMOS set
+pub static CMOS_INSTRUCTIONS: &[Instruction] = &[
+    Instruction::new(0x72, Operation::ADC, AddressingMode::ZeroPageIndirect, 2, 5),
+    Instruction::new(0x32, Operation::AND, AddressingMode::ZeroPageIndirect, 2, 5),
+    Instruction::new(0xd2, Operation::CMP, AddressingMode::ZeroPageIndirect, 2, 5),
+    Instruction::new(0x52, Operation::EOR, AddressingMode::ZeroPageIndirect, 2, 5),
+    Instruction::new(0xb2, Operation::LDA, AddressingMode::ZeroPageIndirect, 2, 5),
+    Instruction::new(0x12, Operation::ORA, AddressingMode::ZeroPageIndirect, 2, 5),
+    Instruction::new(0xf2, Operation::SBC, AddressingMode::ZeroPageIndirect, 2, 5),
+    Instruction::new(0x92, Operation::STA, AddressingMode::ZeroPageIndirect, 2, 5),
+
+    Instruction::new(0x89, Operation::BIT, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x34, Operation::BIT, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x3c, Operation::BIT, AddressingMode::AbsoluteX, 3, 4), // +1 if page crossed
+
+    Instruction::new(0x80, Operation::BRA, AddressingMode::Relative, 2, 2), // always succeeds: +1, +2 if to a new page
+
+    Instruction::new(0x3a, Operation::DEC, AddressingMode::Accumulator, 1, 2),
+    Instruction::new(0x1a, Operation::INC, AddressingMode::Accumulator, 1, 2),
+
+    Instruction::new(0x6c, Operation::JMP, AddressingMode::Indirect, 3, 6),
+    Instruction::new(0x7c, Operation::JMP, AddressingMode::AbsoluteIndexedIndirect, 3, 6),
+
+    Instruction::new(0xda, Operation::PHX, AddressingMode::Implied, 1, 3),
+    Instruction::new(0x5a, Operation::PHY, AddressingMode::Implied, 1, 3),
+    Instruction::new(0xfa, Operation::PLX, AddressingMode::Implied, 1, 4),
+    Instruction::new(0x7a, Operation::PLY, AddressingMode::Implied, 1, 4),
+
+    Instruction::new(0x64, Operation::STZ, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x74, Operation::STZ, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x9c, Operation::STZ, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0x9e, Operation::STZ, AddressingMode::AbsoluteX, 3, 5),
+
+    Instruction::new(0x14, Operation::TRB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x1c, Operation::TRB, AddressingMode::Absolute, 3, 6),
+
+    Instruction::new(0x04, Operation::TSB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x0c, Operation::TSB, AddressingMode::Absolute, 3, 6),
+
+    // Shifts and rotates only take the index fix-up cycle on a page cross
+    Instruction::new(0x1e, Operation::ASL, AddressingMode::AbsoluteX, 3, 6), // +1 if page crossed
+    Instruction::new(0x5e, Operation::LSR, AddressingMode::AbsoluteX, 3, 6), // +1 if page crossed
+    Instruction::new(0x3e, Operation::ROL, AddressingMode::AbsoluteX, 3, 6), // +1 if page crossed
+    Instruction::new(0x7e, Operation::ROR, AddressingMode::AbsoluteX, 3, 6), // +1 if page crossed
+
+    // The bit number is in bits 4-6 of the opcode: RMB0 is $07, RMB1 $17...
+    Instruction::new(0x07, Operation::RMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x17, Operation::RMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x27, Operation::RMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x37, Operation::RMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x47, Operation::RMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x57, Operation::RMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x67, Operation::RMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x77, Operation::RMB, AddressingMode::ZeroPage, 2, 5),
+
+    Instruction::new(0x87, Operation::SMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0x97, Operation::SMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0xa7, Operation::SMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0xb7, Operation::SMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0xc7, Operation::SMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0xd7, Operation::SMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0xe7, Operation::SMB, AddressingMode::ZeroPage, 2, 5),
+    Instruction::new(0xf7, Operation::SMB, AddressingMode::ZeroPage, 2, 5),
+
+    Instruction::new(0x0f, Operation::BBR, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x1f, Operation::BBR, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x2f, Operation::BBR, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x3f, Operation::BBR, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x4f, Operation::BBR, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x5f, Operation::BBR, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x6f, Operation::BBR, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x7f, Operation::BBR, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+
+    Instruction::new(0x8f, Operation::BBS, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0x9f, Operation::BBS, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xaf, Operation::BBS, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xbf, Operation::BBS, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xcf, Operation::BBS, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xdf, Operation::BBS, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xef, Operation::BBS, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+    Instruction::new(0xff, Operation::BBS, AddressingMode::ZeroPageRelative, 3, 5), // +1 if branch succeeds, +2 if to a new page
+
+    Instruction::new(0xcb, Operation::WAI, AddressingMode::Implied, 1, 3),
+    Instruction::new(0xdb, Operation::STP, AddressingMode::Implied, 1, 3),
+
+    // Every other undefined opcode is a NOP of a fixed size
+    Instruction::new(0x03, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x13, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x23, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x33, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x43, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x53, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x63, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x73, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x83, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x93, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xa3, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xb3, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xc3, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xd3, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xe3, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xf3, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x0b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x1b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x2b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x3b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x4b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x5b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x6b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x7b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x8b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x9b, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xab, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xbb, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xeb, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0xfb, Operation::NOP, AddressingMode::Implied, 1, 1),
+    Instruction::new(0x02, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x22, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x42, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x62, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x82, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xc2, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0xe2, Operation::NOP, AddressingMode::Immediate, 2, 2),
+    Instruction::new(0x44, Operation::NOP, AddressingMode::ZeroPage, 2, 3),
+    Instruction::new(0x54, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0xd4, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0xf4, Operation::NOP, AddressingMode::ZeroPageX, 2, 4),
+    Instruction::new(0x5c, Operation::NOP, AddressingMode::Absolute, 3, 8),
+    Instruction::new(0xdc, Operation::NOP, AddressingMode::Absolute, 3, 4),
+    Instruction::new(0xfc, Operation::NOP, AddressingMode::Absolute, 3, 4),
+];
+
+/// Opcode decode table: one slot per opcode, `None` where the opcode is
+/// undefined for the variant
+pub type DecodeTable = [Option<&'static Instruction>; 256];
+
+/// A problem found by `validate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableError {
+    /// The opcode is listed more than once
+    DuplicateOpcode(u8),
+    /// The entry's length disagrees with its addressing mode
+    LengthMismatch(u8),
+}
+
+/// Checks an instruction list for duplicate opcodes and for lengths that
+/// don't match the addressing mode. The decode tables run this at compile
+/// time, so a bad entry fails the build.
+pub const fn validate(instructions: &[Instruction]) -> Result<(), TableError> {
+    let mut seen = [false; 256];
+    let mut i = 0;
+    while i < instructions.len() {
+        let instruction = &instructions[i];
+        if seen[instruction.code as usize] {
+            return Err(TableError::DuplicateOpcode(instruction.code));
         }
-        map
-    };
+        seen[instruction.code as usize] = true;
+        if instruction.length != 1 + instruction.mode.operand_bytes() {
+            return Err(TableError::LengthMismatch(instruction.code));
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+const fn check(instructions: &[Instruction]) {
+    match validate(instructions) {
+        Ok(()) => {}
+        Err(TableError::DuplicateOpcode(_)) => panic!("duplicate opcode in instruction table"),
+        Err(TableError::LengthMismatch(_)) => panic!("instruction length does not match its addressing mode"),
+    }
 }
 
+/// Builds a decode table at compile time. Entries from `overrides` replace
+/// those from `base`.
+const fn build_decode_table(
+    base: &'static [Instruction],
+    official_only: bool,
+    overrides: &'static [Instruction],
+) -> DecodeTable {
+    check(base);
+    check(overrides);
+
+    let mut table: DecodeTable = [None; 256];
+    let mut i = 0;
+    while i < base.len() {
+        if base[i].official || !official_only {
+            table[base[i].code as usize] = Some(&base[i]);
+        }
+        i += 1;
+    }
+    let mut i = 0;
+    while i < overrides.len() {
+        table[overrides[i].code as usize] = Some(&overrides[i]);
+        i += 1;
+    }
+    table
+}
+
+pub static NMOS_DECODE_TABLE: DecodeTable = build_decode_table(INSTRUCTIONS, false, &[]);
+
+/// The official NMOS opcodes with the 65C02 changes applied
+pub static CMOS_DECODE_TABLE: DecodeTable = build_decode_table(INSTRUCTIONS, true, CMOS_INSTRUCTIONS);
+
+/// The opcode decode table for the given CPU variant
+pub fn decode_table(variant: CpuVariant) -> &'static DecodeTable {
+    match variant {
+        CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => &NMOS_DECODE_TABLE,
+        CpuVariant::Wdc65C02 => &CMOS_DECODE_TABLE,
+    }
+}
+
+/// Query API over the instruction set of one CPU variant, the single source
+/// of truth for decoding, disassembling and assembling
+#[derive(Debug, Clone, Copy)]
+pub struct InstructionSet {
+    table: &'static DecodeTable,
+}
+
+impl InstructionSet {
+    pub fn for_variant(variant: CpuVariant) -> Self {
+        InstructionSet {
+            table: decode_table(variant),
+        }
+    }
+
+    /// `None` for opcodes that are undefined on this variant
+    pub fn by_opcode(&self, code: u8) -> Option<&'static Instruction> {
+        self.table[code as usize]
+    }
+
+    /// The encoding of `operation` in `mode`. Some undocumented opcodes
+    /// duplicate an official one (e.g. $EB SBC #imm); the official encoding
+    /// wins.
+    pub fn find(&self, operation: Operation, mode: AddressingMode) -> Option<&'static Instruction> {
+        self.iter()
+            .filter(|instr| instr.operation == operation && instr.mode == mode)
+            .min_by_key(|instr| !instr.official)
+    }
+
+    /// Like `find`, with the mnemonic given as text in either case
+    pub fn by_mnemonic(&self, mnemonic: &str, mode: AddressingMode) -> Option<&'static Instruction> {
+        self.iter()
+            .filter(|instr| instr.mode == mode && instr.operation.to_string().eq_ignore_ascii_case(mnemonic))
+            .min_by_key(|instr| !instr.official)
+    }
+
+    /// Every defined instruction, in opcode order
+    pub fn iter(&self) -> impl Iterator<Item = &'static Instruction> {
+        self.table.iter().filter_map(|instr| *instr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_rejects_bad_tables() {
+        assert_eq!(validate(INSTRUCTIONS), Ok(()));
+        assert_eq!(validate(CMOS_INSTRUCTIONS), Ok(()));
+
+        let duplicate = [
+            Instruction::new(0xf0, Operation::BEQ, AddressingMode::Relative, 2, 2),
+            Instruction::new(0xf0, Operation::BNE, AddressingMode::Relative, 2, 2),
+        ];
+        assert_eq!(validate(&duplicate), Err(TableError::DuplicateOpcode(0xf0)));
+
+        let wrong_length = [Instruction::new(0xa0, Operation::LDY, AddressingMode::Immediate, 3, 2)];
+        assert_eq!(validate(&wrong_length), Err(TableError::LengthMismatch(0xa0)));
+    }
+
+    #[test]
+    fn test_instruction_set_lookups() {
+        let nmos = InstructionSet::for_variant(CpuVariant::Nmos6502);
+        assert_eq!(nmos.iter().count(), 256);
+        assert_eq!(nmos.by_opcode(0xa9).unwrap().operation, Operation::LDA);
+        assert_eq!(nmos.find(Operation::SBC, AddressingMode::Immediate).unwrap().code, 0xe9);
+        assert_eq!(nmos.by_mnemonic("ldy", AddressingMode::AbsoluteX).unwrap().code, 0xbc);
+        assert!(nmos.find(Operation::STZ, AddressingMode::ZeroPage).is_none());
+
+        let cmos = InstructionSet::for_variant(CpuVariant::Wdc65C02);
+        assert_eq!(cmos.iter().count(), 256);
+        assert_eq!(cmos.by_opcode(0x07).unwrap().operation, Operation::RMB);
+        assert_eq!(cmos.by_mnemonic("STZ", AddressingMode::ZeroPage).unwrap().code, 0x64);
+        assert!(cmos.iter().all(|instr| instr.operation != Operation::JAM));
+    }
+}
@@ -0,0 +1,10 @@
+pub mod bus;
+pub mod call_stack;
+pub mod cpu;
+pub mod cpu_error;
+pub mod cpu_state;
+pub mod cpu_types;
+pub mod instruction;
+pub mod loader;
+pub mod mapper;
+pub mod mmio;
@@ -0,0 +1,341 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::cpu::Memory;
+
+/// Object file formats the loader understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Raw bytes, placed at `load_address`
+    Binary { load_address: u16 },
+    /// Commodore-style: a little-endian load address followed by the bytes
+    Prg,
+    IntelHex,
+    /// Motorola S-records (S19/S28/S37)
+    SRecord,
+}
+
+impl Format {
+    /// Picks the format from the file extension, treating anything unknown
+    /// as a raw binary at `load_address`
+    pub fn from_path(path: &Path, load_address: u16) -> Format {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("prg") => Format::Prg,
+            Some("hex") | Some("ihx") => Format::IntelHex,
+            Some("srec") | Some("s19") | Some("s28") | Some("s37") | Some("mot") => Format::SRecord,
+            _ => Format::Binary { load_address },
+        }
+    }
+}
+
+/// Where a program ended up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadedImage {
+    /// Lowest address written
+    pub start: u16,
+    /// Number of bytes written
+    pub len: usize,
+    /// Start address from the file, for the formats that carry one
+    pub entry_point: Option<u16>,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// Malformed record on `line` (1-based)
+    Parse { line: usize, message: &'static str },
+    Checksum { line: usize },
+    /// Data of `len` bytes at `addr` does not fit in the 64KB address space
+    OutOfBounds { addr: u32, len: usize },
+    /// Nothing at `addr` holds the byte loaded there
+    NotWritable { addr: u16 },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LoadError::Checksum { line } => write!(f, "line {}: checksum mismatch", line),
+            LoadError::OutOfBounds { addr, len } => {
+                write!(f, "{} bytes at ${:X} do not fit in memory", len, addr)
+            }
+            LoadError::NotWritable { addr } => write!(f, "nothing at ${:04X} holds the data", addr),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+/// Reads `path` and loads it into `memory`
+pub fn load_file<M: Memory>(memory: &mut M, path: impl AsRef<Path>, format: Format) -> Result<LoadedImage, LoadError> {
+    let data = fs::read(path)?;
+    load_bytes(memory, &data, format)
+}
+
+/// Loads the contents of an object file into `memory`
+pub fn load_bytes<M: Memory>(memory: &mut M, data: &[u8], format: Format) -> Result<LoadedImage, LoadError> {
+    let mut image = Image::new(memory);
+    match format {
+        Format::Binary { load_address } => image.write(load_address as u32, data)?,
+        Format::Prg => {
+            if data.len() < 2 {
+                return Err(LoadError::Parse { line: 1, message: "missing load address header" });
+            }
+            let load_address = u16::from_le_bytes([data[0], data[1]]);
+            image.write(load_address as u32, &data[2..])?;
+        }
+        Format::IntelHex => load_intel_hex(&mut image, text(data)?)?,
+        Format::SRecord => load_srecords(&mut image, text(data)?)?,
+    }
+    Ok(image.finish())
+}
+
+fn text(data: &[u8]) -> Result<&str, LoadError> {
+    std::str::from_utf8(data).map_err(|_| LoadError::Parse { line: 1, message: "not a text file" })
+}
+
+/// Tracks what has been written so far
+struct Image<'a, M: Memory> {
+    memory: &'a mut M,
+    start: Option<u16>,
+    len: usize,
+    entry_point: Option<u16>,
+}
+
+impl<'a, M: Memory> Image<'a, M> {
+    fn new(memory: &'a mut M) -> Self {
+        Image {
+            memory,
+            start: None,
+            len: 0,
+            entry_point: None,
+        }
+    }
+
+    fn write(&mut self, addr: u32, data: &[u8]) -> Result<(), LoadError> {
+        if addr as usize + data.len() > 0x10000 {
+            return Err(LoadError::OutOfBounds { addr, len: data.len() });
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        let addr = addr as u16;
+        for (offset, byte) in data.iter().enumerate() {
+            let addr = addr + offset as u16;
+            if !self.memory.mem_load(addr, *byte) {
+                return Err(LoadError::NotWritable { addr });
+            }
+        }
+        self.start = Some(self.start.map_or(addr, |start| start.min(addr)));
+        self.len += data.len();
+        Ok(())
+    }
+
+    fn set_entry_point(&mut self, line: usize, addr: u32) -> Result<(), LoadError> {
+        let addr = u16::try_from(addr).map_err(|_| LoadError::Parse { line, message: "start address above $FFFF" })?;
+        self.entry_point = Some(addr);
+        Ok(())
+    }
+
+    fn finish(self) -> LoadedImage {
+        LoadedImage {
+            start: self.start.unwrap_or(0),
+            len: self.len,
+            entry_point: self.entry_point,
+        }
+    }
+}
+
+/// Decodes the hex digits of a record, after its start character
+fn record_bytes(digits: &str, line: usize) -> Result<Vec<u8>, LoadError> {
+    if !digits.is_ascii() {
+        return Err(LoadError::Parse { line, message: "invalid hex digit" });
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(LoadError::Parse { line, message: "odd number of hex digits" });
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| LoadError::Parse { line, message: "invalid hex digit" })
+        })
+        .collect()
+}
+
+fn big_endian(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, byte| value << 8 | *byte as u32)
+}
+
+/// https://en.wikipedia.org/wiki/Intel_HEX
+fn load_intel_hex<M: Memory>(image: &mut Image<M>, text: &str) -> Result<(), LoadError> {
+    let mut base: u32 = 0;
+    for (index, record) in text.lines().enumerate() {
+        let line = index + 1;
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let digits = record
+            .strip_prefix(':')
+            .ok_or(LoadError::Parse { line, message: "record does not start with ':'" })?;
+        let bytes = record_bytes(digits, line)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(LoadError::Parse { line, message: "record length does not match its byte count" });
+        }
+        // All bytes including the checksum add up to zero
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(LoadError::Checksum { line });
+        }
+
+        let offset = big_endian(&bytes[1..3]);
+        let data = &bytes[4..bytes.len() - 1];
+        let data_len = match bytes[3] {
+            0x02 | 0x04 => Some(2),
+            0x03 | 0x05 => Some(4),
+            _ => None,
+        };
+        if data_len.is_some_and(|len| len != data.len()) {
+            return Err(LoadError::Parse { line, message: "wrong data length for the record type" });
+        }
+        match bytes[3] {
+            0x00 => image.write(base + offset, data)?,
+            0x01 => return Ok(()),
+            // Extended segment and linear addresses
+            0x02 => base = big_endian(data) << 4,
+            0x04 => base = big_endian(data) << 16,
+            // Start segment (CS:IP) and start linear addresses
+            0x03 => image.set_entry_point(line, (big_endian(&data[..2]) << 4) + big_endian(&data[2..]))?,
+            0x05 => image.set_entry_point(line, big_endian(data))?,
+            _ => return Err(LoadError::Parse { line, message: "unknown record type" }),
+        }
+    }
+    Ok(())
+}
+
+/// https://en.wikipedia.org/wiki/SREC_(file_format)
+fn load_srecords<M: Memory>(image: &mut Image<M>, text: &str) -> Result<(), LoadError> {
+    for (index, record) in text.lines().enumerate() {
+        let line = index + 1;
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let mut chars = record.chars();
+        if chars.next() != Some('S') {
+            return Err(LoadError::Parse { line, message: "record does not start with 'S'" });
+        }
+        let kind = chars.next().ok_or(LoadError::Parse { line, message: "missing record type" })?;
+        let bytes = record_bytes(chars.as_str(), line)?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(LoadError::Parse { line, message: "record length does not match its byte count" });
+        }
+        // The checksum is the ones' complement of the sum of the other bytes
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xFF {
+            return Err(LoadError::Checksum { line });
+        }
+
+        let address_len = match kind {
+            '0' | '1' | '5' | '9' => 2,
+            '2' | '6' | '8' => 3,
+            '3' | '7' => 4,
+            _ => return Err(LoadError::Parse { line, message: "unknown record type" }),
+        };
+        if bytes.len() < address_len + 2 {
+            return Err(LoadError::Parse { line, message: "record too short for its address" });
+        }
+        let addr = big_endian(&bytes[1..1 + address_len]);
+        let data = &bytes[1 + address_len..bytes.len() - 1];
+        match kind {
+            '1' | '2' | '3' => image.write(addr, data)?,
+            '7' | '8' | '9' => image.set_entry_point(line, addr)?,
+            // Header and record counts
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::CPU;
+
+    #[test]
+    fn test_binary_and_prg() {
+        let mut cpu = CPU::new();
+        let image = load_bytes(&mut cpu, &[0xa9, 0x01], Format::Binary { load_address: 0x0600 }).unwrap();
+        assert_eq!(image, LoadedImage { start: 0x0600, len: 2, entry_point: None });
+        assert_eq!(cpu.mem_read(0x0601), 0x01);
+
+        let image = load_bytes(&mut cpu, &[0x01, 0x08, 0xea, 0x60], Format::Prg).unwrap();
+        assert_eq!(image, LoadedImage { start: 0x0801, len: 2, entry_point: None });
+        assert_eq!(cpu.mem_read(0x0802), 0x60);
+
+        assert!(matches!(
+            load_bytes(&mut cpu, &[0; 3], Format::Binary { load_address: 0xfffe }),
+            Err(LoadError::OutOfBounds { addr: 0xfffe, len: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_intel_hex() {
+        let hex = ":03060000A90160ED\n:0400000500000600F1\n:00000001FF\n";
+        let mut cpu = CPU::new();
+        let image = load_bytes(&mut cpu, hex.as_bytes(), Format::IntelHex).unwrap();
+        assert_eq!(image, LoadedImage { start: 0x0600, len: 3, entry_point: Some(0x0600) });
+        assert_eq!(cpu.mem_read(0x0602), 0x60);
+
+        let corrupt = ":03060000A90160EE\n";
+        assert!(matches!(
+            load_bytes(&mut cpu, corrupt.as_bytes(), Format::IntelHex),
+            Err(LoadError::Checksum { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_srecords() {
+        let srec = "S00600004844521B\nS1060600A90160E9\nS5030001FB\nS9030600F6\n";
+        let mut cpu = CPU::new();
+        let image = load_bytes(&mut cpu, srec.as_bytes(), Format::SRecord).unwrap();
+        assert_eq!(image, LoadedImage { start: 0x0600, len: 3, entry_point: Some(0x0600) });
+        assert_eq!(cpu.mem_read(0x0600), 0xa9);
+
+        assert!(matches!(
+            load_bytes(&mut cpu, b"S1060600A90160E8\n", Format::SRecord),
+            Err(LoadError::Checksum { line: 1 })
+        ));
+        assert!(matches!(
+            load_bytes(&mut cpu, b"X1060600A90160E9\n", Format::SRecord),
+            Err(LoadError::Parse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("snake.PRG"), 0), Format::Prg);
+        assert_eq!(Format::from_path(Path::new("rom.hex"), 0), Format::IntelHex);
+        assert_eq!(Format::from_path(Path::new("rom.s19"), 0), Format::SRecord);
+        assert_eq!(Format::from_path(Path::new("rom.bin"), 0x0600), Format::Binary { load_address: 0x0600 });
+    }
+}
@@ -1,8 +1,9 @@
-use crate::cpu::CPU;
+use std::env;
+use std::ops::ControlFlow;
+use std::path::Path;
 
-pub mod cpu;
-mod instruction;
-mod cpu_types;
+use rusty_nes::cpu::{Memory, StopReason, CPU};
+use rusty_nes::loader::{load_file, Format};
 
 fn main() {
     let game_code = vec![
@@ -28,7 +29,43 @@ fn main() {
         0xea, 0xca, 0xd0, 0xfb, 0x60
     ];
 
+    // Usage: RustyNES [program file] [load address in hex, for raw binaries]
+    // Without arguments the built-in snake game runs, assembled for $0600
+    // easy6502 style
+    let args: Vec<String> = env::args().collect();
     let mut nes = CPU::new();
-    nes.load(game_code);
+    match args.get(1) {
+        Some(path) => {
+            let load_address = args
+                .get(2)
+                .map(|addr| u16::from_str_radix(addr.trim_start_matches('$'), 16).expect("invalid load address"))
+                .unwrap_or(0x0600);
+            let format = Format::from_path(Path::new(path), load_address);
+            let image = load_file(&mut nes, path, format).unwrap_or_else(|error| panic!("{}: {}", path, error));
+            nes.set_entry_point(image.entry_point.unwrap_or(image.start)).unwrap();
+        }
+        None => {
+            nes.load_at(0x0600, &game_code).unwrap();
+            nes.set_entry_point(0x0600).unwrap();
+        }
+    }
     nes.reset();
+    nes.stop_on_brk = true;
+
+    // No keyboard yet: the snake runs straight until it hits a wall
+    let mut seed: u32 = 0x2545_f491;
+    let reason = nes.run_with_callback(|cpu| {
+        // xorshift, the game reads a fresh random byte from $FE
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        cpu.mem_write(0xfe, seed as u8);
+        ControlFlow::Continue(())
+    })
+    .unwrap();
+
+    match reason {
+        StopReason::Brk { .. } => println!("stopped at BRK: {}", nes.state()),
+        reason => println!("stopped with {:?}: {}", reason, nes.state()),
+    }
 }
@@ -0,0 +1,75 @@
+/// Cartridge hardware behind $4020-$FFFF. Addresses are the CPU's, not
+/// offsets into the cartridge. Reads return `None` where the cartridge
+/// leaves the data bus alone, which then reads as open bus.
+pub trait Mapper {
+    fn read(&mut self, addr: u16) -> Option<u8>;
+
+    fn write(&mut self, addr: u16, data: u8);
+
+    fn peek(&self, addr: u16) -> Option<u8>;
+
+    /// Stores `data` the way a loader would, into ROM as well. Defaults to
+    /// `write`, which leaves ROM alone.
+    fn poke(&mut self, addr: u16, data: u8) {
+        self.write(addr, data)
+    }
+}
+
+const PRG_RAM_SIZE: usize = 0x2000;
+
+/// Mapper 0: 16KB or 32KB of PRG ROM at $8000, the 16KB kind mirrored into
+/// $C000, plus 8KB of PRG RAM at $6000
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; PRG_RAM_SIZE],
+}
+
+impl Nrom {
+    /// Panics unless `prg_rom` is 16KB or 32KB
+    pub fn new(prg_rom: Vec<u8>) -> Nrom {
+        assert!(
+            prg_rom.len() == 0x4000 || prg_rom.len() == 0x8000,
+            "NROM takes 16KB or 32KB of PRG ROM, not {} bytes",
+            prg_rom.len()
+        );
+        Nrom { prg_rom, prg_ram: [0; PRG_RAM_SIZE] }
+    }
+}
+
+/// A blank 32KB cartridge, for loading programs into with `CPU::load`
+impl Default for Nrom {
+    fn default() -> Self {
+        Self::new(vec![0; 0x8000])
+    }
+}
+
+impl Mapper for Nrom {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        // Writes to ROM go nowhere
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = data;
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0x8000..=0xFFFF => Some(self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()]),
+            _ => None,
+        }
+    }
+
+    fn poke(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x8000..=0xFFFF => {
+                let len = self.prg_rom.len();
+                self.prg_rom[(addr - 0x8000) as usize % len] = data;
+            }
+            _ => self.write(addr, data),
+        }
+    }
+}
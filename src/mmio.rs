@@ -0,0 +1,404 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use crate::bus::{Bus, FlatRam, OpenBus};
+
+/// A memory-mapped device. `offset` is relative to the start of the range
+/// the device was mapped at.
+pub trait MmioDevice {
+    fn read(&mut self, offset: u16) -> u8;
+
+    fn write(&mut self, offset: u16, data: u8);
+
+    /// `read` without side effects
+    fn peek(&self, offset: u16) -> u8;
+
+    /// Bits the device drives when `offset` is read, the others read as open
+    /// bus
+    fn driven_bits(&self, _offset: u16) -> u8 {
+        0xFF
+    }
+
+    /// Called on every bus tick, even while another mapping shadows the
+    /// device
+    fn tick(&mut self, _cycles: u8) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceId(usize);
+
+/// One entry of the memory map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub id: DeviceId,
+    pub name: String,
+    pub start: u16,
+    pub end: u16,
+    /// Where ranges overlap the highest priority wins, and among equals the
+    /// device mapped last
+    pub priority: i8,
+}
+
+impl Mapping {
+    pub fn contains(&self, addr: u16) -> bool {
+        (self.start..=self.end).contains(&addr)
+    }
+}
+
+/// `$2000-$2007 ppu (priority 0)`
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${:04X}-${:04X} {} (priority {})", self.start, self.end, self.name, self.priority)
+    }
+}
+
+/// A bus that devices can be mapped onto at runtime. Addresses no device
+/// claims go to the wrapped `fallback` bus.
+pub struct MmioBus<B: Bus = FlatRam> {
+    fallback: B,
+    /// Only used when the fallback bus has no open bus latch to share
+    open_bus: OpenBus,
+    devices: Vec<Option<Box<dyn MmioDevice>>>,
+    /// In lookup order: highest priority first, newest first among equals
+    mappings: Vec<Mapping>,
+}
+
+impl<B: Bus> MmioBus<B> {
+    pub fn new(fallback: B) -> MmioBus<B> {
+        MmioBus { fallback, open_bus: OpenBus::default(), devices: vec![], mappings: vec![] }
+    }
+
+    /// Maps `device` over `range`. Panics if `range` is inverted.
+    pub fn map<D>(&mut self, name: &str, range: RangeInclusive<u16>, priority: i8, device: D) -> DeviceId
+    where
+        D: MmioDevice + 'static,
+    {
+        assert!(
+            range.start() <= range.end(),
+            "{} mapped over an inverted range ${:04X}-${:04X}",
+            name,
+            range.start(),
+            range.end()
+        );
+        let id = DeviceId(self.devices.len());
+        self.devices.push(Some(Box::new(device)));
+        let index = self.mappings.iter().position(|mapping| mapping.priority <= priority)
+            .unwrap_or(self.mappings.len());
+        self.mappings.insert(index, Mapping {
+            id,
+            name: name.to_string(),
+            start: *range.start(),
+            end: *range.end(),
+            priority,
+        });
+        id
+    }
+
+    /// Takes a device off the bus, uncovering whatever it shadowed
+    pub fn unmap(&mut self, id: DeviceId) -> Option<Box<dyn MmioDevice>> {
+        self.mappings.retain(|mapping| mapping.id != id);
+        self.devices.get_mut(id.0)?.take()
+    }
+
+    pub fn device(&self, id: DeviceId) -> Option<&dyn MmioDevice> {
+        self.devices.get(id.0)?.as_deref()
+    }
+
+    pub fn device_mut(&mut self, id: DeviceId) -> Option<&mut (dyn MmioDevice + 'static)> {
+        self.devices.get_mut(id.0)?.as_deref_mut()
+    }
+
+    /// Every mapping, ordered by start address and then by priority
+    pub fn memory_map(&self) -> Vec<&Mapping> {
+        let mut map: Vec<&Mapping> = self.mappings.iter().collect();
+        map.sort_by_key(|mapping| (mapping.start, -(mapping.priority as i16)));
+        map
+    }
+
+    /// The mapping that answers for `addr`, `None` for the fallback bus
+    pub fn resolve(&self, addr: u16) -> Option<&Mapping> {
+        self.mappings.iter().find(|mapping| mapping.contains(addr))
+    }
+
+    pub fn fallback(&self) -> &B {
+        &self.fallback
+    }
+
+    pub fn fallback_mut(&mut self) -> &mut B {
+        &mut self.fallback
+    }
+
+    /// The open bus latch, the fallback's if it keeps one so that devices
+    /// and the fallback see the same value
+    fn latch(&self) -> &OpenBus {
+        self.fallback.open_bus().unwrap_or(&self.open_bus)
+    }
+
+    fn latch_mut(&mut self) -> &mut OpenBus {
+        match self.fallback.open_bus_mut() {
+            Some(open_bus) => open_bus,
+            None => &mut self.open_bus,
+        }
+    }
+
+    fn lookup(&self, addr: u16) -> Option<(usize, u16)> {
+        self.resolve(addr).map(|mapping| (mapping.id.0, addr - mapping.start))
+    }
+}
+
+impl<B: Bus> Bus for MmioBus<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        match self.lookup(addr) {
+            Some((index, offset)) => {
+                let device = self.devices[index].as_mut().expect("mapped devices exist");
+                let (data, driven) = (device.read(offset), device.driven_bits(offset));
+                let open_bus = self.latch_mut();
+                let data = open_bus.merge(data, driven);
+                open_bus.latch(data)
+            }
+            // A fallback with a latch decides for itself what to latch
+            None if self.fallback.open_bus().is_some() => self.fallback.read(addr),
+            None => {
+                let data = self.fallback.read(addr);
+                self.open_bus.latch(data)
+            }
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.latch_mut().latch(data);
+        match self.lookup(addr) {
+            Some((index, offset)) => self.devices[index].as_mut().expect("mapped devices exist").write(offset, data),
+            None => self.fallback.write(addr, data),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match self.lookup(addr) {
+            Some((index, offset)) => {
+                let device = self.devices[index].as_ref().expect("mapped devices exist");
+                self.latch().merge(device.peek(offset), device.driven_bits(offset))
+            }
+            None => self.fallback.peek(addr),
+        }
+    }
+
+    fn poke(&mut self, addr: u16, data: u8) {
+        match self.lookup(addr) {
+            Some((index, offset)) => self.devices[index].as_mut().expect("mapped devices exist").write(offset, data),
+            None => self.fallback.poke(addr, data),
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for device in self.devices.iter_mut().flatten() {
+            device.tick(cycles);
+        }
+        self.fallback.tick(cycles);
+    }
+
+    fn open_bus(&self) -> Option<&OpenBus> {
+        Some(self.latch())
+    }
+
+    fn open_bus_mut(&mut self) -> Option<&mut OpenBus> {
+        Some(self.latch_mut())
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.fallback.ram_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use crate::bus::NesBus;
+    use crate::cpu::{Memory, CPU};
+    use crate::cpu_types::CpuVariant;
+    use crate::mapper::Nrom;
+
+    /// A bank of plain registers that counts the cycles it sees
+    struct Registers {
+        data: Vec<u8>,
+        cycles: Rc<Cell<u64>>,
+    }
+
+    impl Registers {
+        fn new(len: usize) -> Registers {
+            Registers { data: vec![0; len], cycles: Rc::default() }
+        }
+    }
+
+    impl MmioDevice for Registers {
+        fn read(&mut self, offset: u16) -> u8 {
+            self.data[offset as usize]
+        }
+
+        fn write(&mut self, offset: u16, data: u8) {
+            self.data[offset as usize] = data;
+        }
+
+        fn peek(&self, offset: u16) -> u8 {
+            self.data[offset as usize]
+        }
+
+        fn tick(&mut self, cycles: u8) {
+            self.cycles.set(self.cycles.get() + cycles as u64);
+        }
+    }
+
+    /// Reads back a fixed value and ignores writes
+    struct Constant(u8);
+
+    impl MmioDevice for Constant {
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.0
+        }
+
+        fn write(&mut self, _offset: u16, _data: u8) {}
+
+        fn peek(&self, _offset: u16) -> u8 {
+            self.0
+        }
+    }
+
+    /// Only drives the low nibble, like a 4-bit latch
+    struct Nibble(u8);
+
+    impl MmioDevice for Nibble {
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.0
+        }
+
+        fn write(&mut self, _offset: u16, data: u8) {
+            self.0 = data;
+        }
+
+        fn peek(&self, _offset: u16) -> u8 {
+            self.0
+        }
+
+        fn driven_bits(&self, _offset: u16) -> u8 {
+            0x0F
+        }
+    }
+
+    #[test]
+    fn test_devices_see_offsets() {
+        let mut bus = MmioBus::new(FlatRam::new());
+        let id = bus.map("regs", 0x5000..=0x5003, 0, Registers::new(4));
+        bus.write(0x5002, 0x77);
+        assert_eq!(bus.device(id).unwrap().peek(2), 0x77);
+        assert_eq!(bus.read(0x5002), 0x77);
+        // Outside the range the fallback answers
+        bus.write(0x5004, 0x11);
+        assert_eq!(bus.fallback().peek(0x5004), 0x11);
+    }
+
+    #[test]
+    fn test_overlaps_go_by_priority_then_recency() {
+        let mut bus = MmioBus::new(FlatRam::new());
+        bus.map("low", 0x6000..=0x60ff, -1, Constant(0x01));
+        let high = bus.map("high", 0x6080..=0x608f, 5, Constant(0x02));
+        bus.map("later low", 0x6000..=0x60ff, -1, Constant(0x03));
+        bus.map("later high", 0x6088..=0x6088, 5, Constant(0x04));
+
+        assert_eq!(bus.read(0x6000), 0x03);
+        assert_eq!(bus.read(0x6080), 0x02);
+        assert_eq!(bus.read(0x6088), 0x04);
+        assert_eq!(bus.resolve(0x6081).unwrap().name, "high");
+
+        assert!(bus.unmap(high).is_some());
+        assert_eq!(bus.read(0x6080), 0x03);
+        assert!(bus.unmap(high).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "inverted range")]
+    fn test_inverted_range_is_rejected() {
+        let mut bus = MmioBus::new(FlatRam::new());
+        bus.map("backwards", RangeInclusive::new(0x5003, 0x5000), 0, Constant(0));
+    }
+
+    #[test]
+    fn test_memory_map_listing() {
+        let mut bus = MmioBus::new(FlatRam::new());
+        bus.map("mapper", 0x8000..=0xffff, 0, Constant(0));
+        bus.map("ppu", 0x2000..=0x3fff, 0, Constant(0));
+        bus.map("debug port", 0x8000..=0x8000, 1, Constant(0));
+
+        let map: Vec<String> = bus.memory_map().iter().map(|mapping| mapping.to_string()).collect();
+        assert_eq!(
+            map,
+            vec![
+                "$2000-$3FFF ppu (priority 0)",
+                "$8000-$8000 debug port (priority 1)",
+                "$8000-$FFFF mapper (priority 0)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cpu_drives_a_mapped_device() {
+        let mut bus = MmioBus::new(FlatRam::new());
+        let id = bus.map("regs", 0x4800..=0x48ff, 0, Registers::new(0x100));
+        let mut cpu = CPU::with_bus(CpuVariant::Ricoh2A03, bus);
+        // LDA #$2a; STA $4810; INC $4810; LDX $4810
+        cpu.load(vec![0xa9, 0x2a, 0x8d, 0x10, 0x48, 0xee, 0x10, 0x48, 0xae, 0x10, 0x48]);
+        cpu.reset();
+        cpu.run_instructions(4);
+
+        assert_eq!(cpu.register_x, 0x2b);
+        assert_eq!(cpu.mem_read(0x4810), 0x2b);
+        assert_eq!(cpu.bus().device(id).unwrap().peek(0x10), 0x2b);
+        assert_eq!(cpu.bus().fallback().peek(0x4810), 0x00);
+    }
+
+    #[test]
+    fn test_tick_reaches_every_device() {
+        let registers = Registers::new(1);
+        let cycles = registers.cycles.clone();
+        let mut bus = MmioBus::new(FlatRam::new());
+        bus.map("regs", 0x5000..=0x5000, 0, registers);
+        bus.map("shadow", 0x5000..=0x5000, 1, Constant(0));
+        bus.tick(3);
+        bus.tick(4);
+        // Shadowed devices keep running
+        assert_eq!(cycles.get(), 7);
+    }
+
+    #[test]
+    fn test_undriven_bits_read_as_open_bus() {
+        let mut bus = MmioBus::new(FlatRam::new());
+        bus.map("nibble", 0x5000..=0x5000, 0, Nibble(0));
+        bus.write(0x5000, 0x0c);
+        bus.write(0x0010, 0xa0);
+        assert_eq!(bus.read(0x0010), 0xa0);
+        assert_eq!(bus.peek(0x5000), 0xac);
+        assert_eq!(bus.read(0x5000), 0xac);
+        bus.write(0x0010, 0x30);
+        bus.read(0x0010);
+        assert_eq!(bus.read(0x5000), 0x3c);
+    }
+
+    #[test]
+    fn test_open_bus_is_shared_with_the_nes_bus() {
+        let mut bus = MmioBus::new(NesBus::new(Nrom::new(vec![0; 0x4000])));
+        bus.map("nibble", 0x4800..=0x4800, 0, Nibble(0x05));
+        // The NES bus answers $5000 with whatever the device last drove
+        bus.read(0x4800);
+        assert_eq!(bus.read(0x5000), 0x05);
+        // and the device's undriven bits see what the NES bus latched
+        bus.write(0x0010, 0xa0);
+        assert_eq!(bus.read(0x0010), 0xa0);
+        assert_eq!(bus.read(0x4800), 0xa5);
+        // The $4015 read stays inside the 2A03
+        bus.write(0x4015, 0x0f);
+        bus.write(0x0010, 0x60);
+        bus.read(0x0010);
+        assert_eq!(bus.read(0x4015), 0x2f);
+        assert_eq!(bus.read(0x4800), 0x65);
+    }
+}